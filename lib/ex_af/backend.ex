@@ -29,6 +29,7 @@ defmodule ExAF.Backend do
     tensor
     |> from_nx
     |> Native.backend_deallocate()
+    |> unwrap!()
   end
 
   @impl true
@@ -55,6 +56,7 @@ defmodule ExAF.Backend do
     tensor
     |> from_nx
     |> Native.to_binary(limit)
+    |> unwrap!()
   end
 
   @impl true
@@ -102,67 +104,15 @@ defmodule ExAF.Backend do
   end
 
   @impl true
-  def iota(%T{shape: {}} = out, nil, backend_opts) do
-    constant(out, 0, backend_opts)
-  end
-
-  @impl true
-  def iota(out, nil, _backend_opts) do
+  def iota(out, axis, _backend_opts) do
     shape = to_exaf_shape(out.shape)
     type = to_exaf_type(out.type)
-    tdims = to_exaf_shape({1, 1, 1, 1})
 
     shape
-    |> Native.iota(tdims, type)
+    |> Native.iota(axis, type)
     |> to_nx(out)
   end
 
-  @impl true
-  def iota(out, axis, _backend_opts) when axis == tuple_size(out.shape) - 1 do
-    {tdims, shape} =
-      out.shape
-      |> Tuple.to_list()
-      |> Enum.split(axis)
-
-    type = to_exaf_type(out.type)
-    tdims = to_exaf_shape(tdims)
-
-    shape
-    |> to_exaf_shape()
-    |> Native.iota(tdims, type)
-    |> to_nx(out)
-  end
-
-  # TODO: Rewrite in an ArrayFire native manner
-  # Copied from Nx.BinaryBackend
-
-  @impl true
-  def iota(%{shape: shape, type: type} = out, axis, _backend_options) do
-    {dims_before, [dim | dims_after]} =
-      shape
-      |> Tuple.to_list()
-      |> Enum.split(axis)
-
-    # Number of repetitions of an index in memory
-    repeat_blocks =
-      dims_after
-      |> Enum.reduce(1, &*/2)
-
-    # Number of cycles of the counting pattern
-    cycles =
-      dims_before
-      |> Enum.reduce(1, &*/2)
-
-    data =
-      for _ <- 1..cycles,
-          i <- 0..(dim - 1),
-          _ <- 1..repeat_blocks,
-          into: "",
-          do: number_to_binary(i, type)
-
-    from_binary(out, data)
-  end
-
   # Elementwise
 
   for op <- unary_ops() do
@@ -173,6 +123,7 @@ defmodule ExAF.Backend do
       tensor
       |> from_nx()
       |> Native.unquote(op)()
+      |> unwrap!()
       |> Native.as_type(type)
       |> to_nx(out)
     end
@@ -184,8 +135,9 @@ defmodule ExAF.Backend do
       type = to_exaf_type(out.type)
 
       l
-      |> from_nx()
-      |> Native.unquote(op)(from_nx(r))
+      |> maybe_broadcast(out.shape)
+      |> Native.unquote(op)(maybe_broadcast(r, out.shape))
+      |> unwrap!()
       |> Native.as_type(type)
       |> to_nx(out)
     end
@@ -197,11 +149,12 @@ defmodule ExAF.Backend do
   def atan2(out, l, r) do
     type = to_exaf_type(out.type)
 
-    left = to_floating(l)
-    right = to_floating(r)
+    left = l |> to_floating() |> maybe_broadcast(l.shape, out.shape)
+    right = r |> to_floating() |> maybe_broadcast(r.shape, out.shape)
 
     left
     |> Native.atan2(right)
+    |> unwrap!()
     |> Native.as_type(type)
     |> to_nx(out)
   end
@@ -211,10 +164,391 @@ defmodule ExAF.Backend do
     |> Type.merge({:f, 64})
     |> to_exaf_type
     |> then(&Native.as_type(from_nx(tensor), &1))
+    |> unwrap!()
+  end
+
+  # Binary ops receive tensors of different shapes and
+  # expect the backend to broadcast them to the output shape.
+  defp maybe_broadcast(%T{shape: shape} = tensor, shape), do: from_nx(tensor)
+
+  defp maybe_broadcast(%T{shape: shape} = tensor, out_shape) do
+    tensor
+    |> from_nx()
+    |> maybe_broadcast(shape, out_shape)
+  end
+
+  defp maybe_broadcast(ref, shape, shape), do: ref
+
+  defp maybe_broadcast(ref, shape, out_shape) do
+    axes = Nx.Shape.broadcast_axes(shape, out_shape)
+
+    ref
+    |> Native.broadcast(to_exaf_shape(out_shape), axes)
+    |> unwrap!()
+  end
+
+  # Aggregates
+
+  # Sums and products are computed in the output type, so
+  # that they overflow the same way as they do in Nx.
+  for op <- [:sum, :product] do
+    @impl true
+    def unquote(op)(out, tensor, opts) do
+      type = to_exaf_type(out.type)
+
+      tensor
+      |> from_nx()
+      |> Native.as_type(type)
+      |> unwrap!()
+      |> reduce(unquote(op), out, opts)
+    end
+  end
+
+  for op <- reduction_ops() -- [:sum, :product] do
+    @impl true
+    def unquote(op)(out, tensor, opts) do
+      tensor
+      |> from_nx()
+      |> reduce(unquote(op), out, opts)
+    end
+  end
+
+  defp reduce(ref, op, out, opts) do
+    type = to_exaf_type(out.type)
+    axes = opts[:axes]
+    keep_axes = Keyword.get(opts, :keep_axes, false)
+
+    Native
+    |> apply(op, [ref, axes, keep_axes])
+    |> unwrap!()
+    |> Native.as_type(type)
+    |> to_nx(out)
+  end
+
+  for op <- [:argmax, :argmin] do
+    @impl true
+    def unquote(op)(out, tensor, opts) do
+      type = to_exaf_type(out.type)
+      tie_break = Keyword.get(opts, :tie_break, :low)
+      keep_axis = Keyword.get(opts, :keep_axis, false)
+
+      tensor
+      |> from_nx()
+      |> Native.unquote(op)(opts[:axis], tie_break, keep_axis)
+      |> unwrap!()
+      |> Native.as_type(type)
+      |> to_nx(out)
+    end
+  end
+
+  for op <- [:cumulative_sum, :cumulative_product, :cumulative_max, :cumulative_min] do
+    @impl true
+    def unquote(op)(out, tensor, opts) do
+      type = to_exaf_type(out.type)
+
+      tensor
+      |> from_nx()
+      |> Native.as_type(type)
+      |> unwrap!()
+      |> Native.unquote(op)(opts[:axis], Keyword.get(opts, :reverse, false))
+      |> to_nx(out)
+    end
+  end
+
+  for op <- [:window_sum, :window_product, :window_max, :window_min] do
+    @impl true
+    def unquote(op)(out, tensor, window_dimensions, opts) do
+      type = to_exaf_type(out.type)
+      window_dimensions = Tuple.to_list(window_dimensions)
+      ones = List.duplicate(1, length(window_dimensions))
+
+      tensor
+      |> from_nx()
+      |> Native.as_type(type)
+      |> unwrap!()
+      |> Native.unquote(op)(
+        window_dimensions,
+        Keyword.get(opts, :strides, ones),
+        opts[:padding],
+        Keyword.get(opts, :window_dilations, ones)
+      )
+      |> to_nx(out)
+    end
+  end
+
+  for op <- [:window_scatter_max, :window_scatter_min] do
+    @impl true
+    def unquote(op)(out, tensor, source, init_value, window_dimensions, opts) do
+      type = to_exaf_type(out.type)
+      window_dimensions = Tuple.to_list(window_dimensions)
+      ones = List.duplicate(1, length(window_dimensions))
+
+      [tensor, source, init_value] =
+        for t <- [tensor, source, init_value] do
+          t |> from_nx() |> Native.as_type(type) |> unwrap!()
+        end
+
+      tensor
+      |> Native.unquote(op)(
+        source,
+        init_value,
+        window_dimensions,
+        Keyword.get(opts, :strides, ones),
+        opts[:padding]
+      )
+      |> to_nx(out)
+    end
+  end
+
+  # Sorting
+
+  @impl true
+  def sort(out, tensor, opts) do
+    tensor
+    |> from_nx()
+    |> Native.sort(opts[:axis], opts[:direction] == :desc)
+    |> to_nx(out)
+  end
+
+  @impl true
+  def argsort(out, tensor, opts) do
+    type = to_exaf_type(out.type)
+
+    tensor
+    |> from_nx()
+    |> Native.argsort(opts[:axis], opts[:direction] == :desc)
+    |> unwrap!()
+    |> Native.as_type(type)
+    |> to_nx(out)
+  end
+
+  # top_k is an optional callback, Nx falls back to argsort
+  # for backends that don't implement it.
+  def top_k({values_out, indices_out}, tensor, opts) do
+    {values, indices} =
+      tensor
+      |> from_nx()
+      |> Native.top_k(opts[:k])
+      |> unwrap!()
+
+    indices = indices |> Native.as_type(to_exaf_type(indices_out.type)) |> unwrap!()
+
+    {to_nx(values, values_out), to_nx(indices, indices_out)}
+  end
+
+  # Linear Algebra
+
+  @impl true
+  def dot(out, left, contract_axes1, batch_axes1, right, contract_axes2, batch_axes2) do
+    left = from_nx(left, out.type)
+    right = from_nx(right, out.type)
+
+    left
+    |> Native.dot(contract_axes1, batch_axes1, right, contract_axes2, batch_axes2)
+    |> to_nx(out)
+  end
+
+  @impl true
+  def lu({p, l, u}, tensor, _opts) do
+    {p_ref, l_ref, u_ref} =
+      tensor
+      |> from_nx(p.type)
+      |> Native.lu()
+      |> unwrap!()
+
+    {to_nx(p_ref, p), to_nx(l_ref, l), to_nx(u_ref, u)}
+  end
+
+  @impl true
+  def qr({q, r}, tensor, opts) do
+    {q_ref, r_ref} =
+      tensor
+      |> from_nx(q.type)
+      |> Native.qr(opts[:mode] == :complete)
+      |> unwrap!()
+
+    {to_nx(q_ref, q), to_nx(r_ref, r)}
+  end
+
+  @impl true
+  def cholesky(out, tensor) do
+    tensor
+    |> from_nx(out.type)
+    |> Native.cholesky()
+    |> to_nx(out)
+  end
+
+  @impl true
+  def triangular_solve(out, a, b, opts) do
+    left_side = Keyword.get(opts, :left_side, true)
+    lower = Keyword.get(opts, :lower, true)
+    transpose_a = Keyword.get(opts, :transform_a, :none) == :transpose
+
+    a
+    |> from_nx(out.type)
+    |> Native.triangular_solve(from_nx(b, out.type), left_side, lower, transpose_a)
+    |> to_nx(out)
+  end
+
+  # LAPACK computes the decomposition directly, so the :max_iter
+  # and :eps options don't apply to svd.
+
+  @impl true
+  def svd({u, s, vt}, tensor, _opts) do
+    rank = tuple_size(tensor.shape)
+    m = elem(tensor.shape, rank - 2)
+    n = elem(tensor.shape, rank - 1)
+    full_matrices = elem(u.shape, rank - 1) == m and elem(vt.shape, rank - 2) == n
+
+    {u_ref, s_ref, vt_ref} =
+      tensor
+      |> from_nx(u.type)
+      |> Native.svd(full_matrices)
+      |> unwrap!()
+
+    # Singular values are computed in the type of the input.
+    s_ref = s_ref |> Native.as_type(to_exaf_type(s.type)) |> unwrap!()
+
+    {to_nx(u_ref, u), to_nx(s_ref, s), to_nx(vt_ref, vt)}
+  end
+
+  @impl true
+  def eigh({eigenvals, eigenvecs}, tensor, opts) do
+    {eigenvals_ref, eigenvecs_ref} =
+      tensor
+      |> from_nx(eigenvecs.type)
+      |> Native.eigh(Keyword.get(opts, :max_iter, 50_000), Keyword.get(opts, :eps, 1.0e-10))
+      |> unwrap!()
+
+    {to_nx(eigenvals_ref, eigenvals), to_nx(eigenvecs_ref, eigenvecs)}
+  end
+
+  # solve, invert and determinant are optional callbacks, which
+  # Nx otherwise computes through the decompositions.
+
+  def solve(out, a, b) do
+    a
+    |> from_nx(out.type)
+    |> Native.solve(from_nx(b, out.type))
+    |> to_nx(out)
+  end
+
+  def invert(out, tensor) do
+    tensor
+    |> from_nx(out.type)
+    |> Native.invert()
+    |> to_nx(out)
+  end
+
+  def determinant(out, tensor) do
+    tensor
+    |> from_nx(out.type)
+    |> Native.determinant()
+    |> to_nx(out)
+  end
+
+  # Indexing
+
+  @impl true
+  def slice(out, tensor, start_indices, lengths, strides) do
+    start_indices = clamp_indices(start_indices, tensor.shape, lengths)
+
+    tensor
+    |> from_nx()
+    |> Native.slice(start_indices, lengths, strides)
+    |> to_nx(out)
+  end
+
+  @impl true
+  def put_slice(out, tensor, start_indices, slice) do
+    start_indices = clamp_indices(start_indices, tensor.shape, Tuple.to_list(slice.shape))
+
+    tensor
+    |> from_nx(out.type)
+    |> Native.put_slice(start_indices, from_nx(slice, out.type))
+    |> to_nx(out)
+  end
+
+  @impl true
+  def pad(out, tensor, pad_value, config) do
+    tensor
+    |> from_nx(out.type)
+    |> Native.pad(from_nx(pad_value, out.type), config)
+    |> to_nx(out)
+  end
+
+  for op <- [:take, :take_along_axis] do
+    @impl true
+    def unquote(op)(out, tensor, indices, axis) do
+      tensor
+      |> from_nx()
+      |> Native.unquote(op)(from_nx(indices), axis)
+      |> to_nx(out)
+    end
+  end
+
+  @impl true
+  def gather(out, tensor, indices) do
+    tensor
+    |> from_nx()
+    |> Native.gather(from_nx(indices))
+    |> to_nx(out)
+  end
+
+  for op <- [:indexed_add, :indexed_put] do
+    @impl true
+    def unquote(op)(out, target, indices, updates) do
+      target
+      |> from_nx(out.type)
+      |> Native.unquote(op)(from_nx(indices), from_nx(updates, out.type))
+      |> to_nx(out)
+    end
+  end
+
+  # Start indices may be given as tensors, which Nx clamps
+  # so that the slice fits within the tensor.
+  defp clamp_indices(start_indices, shape, lengths) do
+    [Tuple.to_list(shape), start_indices, lengths]
+    |> Enum.zip_with(fn [dim, start, length] ->
+      start
+      |> Nx.to_number()
+      |> max(0)
+      |> min(dim - length)
+    end)
+  end
+
+  # Convolution
+
+  @impl true
+  def conv(out, tensor, kernel, opts) do
+    tensor
+    |> from_nx(out.type)
+    |> Native.conv(from_nx(kernel, out.type), Map.new(opts))
+    |> to_nx(out)
+  end
+
+  # FFT
+
+  for op <- [:fft, :ifft] do
+    @impl true
+    def unquote(op)(out, tensor, opts) do
+      tensor
+      |> from_nx(out.type)
+      |> Native.unquote(op)(opts[:length])
+      |> to_nx(out)
+    end
   end
 
   # Shape
 
+  @impl true
+  def broadcast(out, tensor, shape, axes) do
+    tensor
+    |> from_nx()
+    |> Native.broadcast(to_exaf_shape(shape), axes)
+    |> to_nx(out)
+  end
+
   @impl true
   def reshape(out, tensor) do
     shape = to_exaf_shape(out.shape)
@@ -225,6 +559,37 @@ defmodule ExAF.Backend do
     |> to_nx(out)
   end
 
+  @impl true
+  def transpose(out, tensor, axes) do
+    tensor
+    |> from_nx()
+    |> Native.transpose(axes)
+    |> to_nx(out)
+  end
+
+  @impl true
+  def concatenate(out, tensors, axis) do
+    tensors
+    |> Enum.map(&from_nx/1)
+    |> Native.concatenate(axis, to_exaf_type(out.type))
+    |> to_nx(out)
+  end
+
+  @impl true
+  def stack(out, tensors, axis) do
+    tensors
+    |> Enum.map(fn tensor ->
+      shape = tensor.shape |> Tuple.insert_at(axis, 1) |> to_exaf_shape()
+
+      tensor
+      |> from_nx()
+      |> Native.reshape(shape)
+      |> unwrap!()
+    end)
+    |> Native.concatenate(axis, to_exaf_type(out.type))
+    |> to_nx(out)
+  end
+
   # Type
 
   @impl true
@@ -28,8 +28,39 @@ defmodule ExAF.Helpers do
     ref
   end
 
+  # Tensors are only cast to the given type when theirs differs.
+  def from_nx(%T{type: type} = tensor, type) do
+    from_nx(tensor)
+  end
+
+  def from_nx(tensor, type) do
+    tensor
+    |> from_nx()
+    |> ExAF.Native.as_type(to_exaf_type(type))
+    |> unwrap!()
+  end
+
   def to_nx(ref, %T{type: _type, shape: _shape} = t) do
-    %{t | data: ref}
+    %{t | data: unwrap!(ref)}
+  end
+
+  # NIFs return {:error, reason} instead of raising, so that
+  # ArrayFire errors surface as Elixir exceptions.
+
+  def unwrap!({:error, :already_deallocated}) do
+    raise ArgumentError, "ExAF tensor has been deallocated"
+  end
+
+  def unwrap!({:error, :singular_matrix}) do
+    raise ArgumentError, "can't solve for singular matrix"
+  end
+
+  def unwrap!({:error, reason}) do
+    raise RuntimeError, reason
+  end
+
+  def unwrap!(result) do
+    result
   end
 
   # Callback listing functions
@@ -38,16 +69,21 @@ defmodule ExAF.Helpers do
     [:exp, :expm1, :log, :log1p, :sigmoid] ++
       [:sin, :cos, :tan, :sinh, :cosh, :tanh, :asin, :acos, :atan, :asinh, :acosh, :atanh] ++
       [:erf, :erfc] ++
-      [:sqrt, :rsqrt, :cbrt] ++ [:abs, :floor, :round, :ceil, :real, :imag]
+      [:sqrt, :rsqrt, :cbrt] ++ [:abs, :floor, :round, :ceil, :real, :imag] ++
+      [:bitwise_not, :population_count, :count_leading_zeros]
   end
 
   def binary_ops() do
     [:add, :subtract, :multiply, :power, :remainder, :divide, :min, :max, :atan2] ++
-      [:left_shift, :right_shift] ++
+      [:left_shift, :right_shift] ++ [:bitwise_and, :bitwise_or, :bitwise_xor] ++
       [:equal, :not_equal, :greater, :less, :greater_equal, :less_equal] ++
       [:logical_and, :logical_or]
   end
 
+  def reduction_ops() do
+    [:sum, :product, :reduce_max, :reduce_min, :all, :any]
+  end
+
   # Validation and to_exaf_* type functions
 
   def to_exaf_type(type) do
@@ -68,14 +104,10 @@ defmodule ExAF.Helpers do
     |> to_exaf_shape
   end
 
+  # Shapes are passed to ExAF.Native in Nx order, the conversion
+  # to ArrayFire's column-major dims is done natively.
   def to_exaf_shape(shape) when is_list(shape) do
-    case length(shape) do
-      len when len > 4 ->
-        raise ArgumentError, "ExAF does not support #{len} dimensional tensors"
-
-      len ->
-        shape ++ List.duplicate(1, 4 - len)
-    end
+    shape
   end
 
   def number_to_binary(number, type) do
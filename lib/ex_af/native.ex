@@ -29,13 +29,83 @@ defmodule ExAF.Native do
     def unquote(op)(_, _), do: error()
   end
 
+  # Aggregates
+
+  for op <- Helpers.reduction_ops() do
+    def unquote(op)(_, _, _), do: error()
+  end
+
+  def argmax(_, _, _, _), do: error()
+  def argmin(_, _, _, _), do: error()
+
+  for op <- [:cumulative_sum, :cumulative_product, :cumulative_max, :cumulative_min] do
+    def unquote(op)(_, _, _), do: error()
+  end
+
+  for op <- [:window_sum, :window_product, :window_max, :window_min] do
+    def unquote(op)(_, _, _, _, _), do: error()
+  end
+
+  def window_scatter_max(_, _, _, _, _, _), do: error()
+  def window_scatter_min(_, _, _, _, _, _), do: error()
+
+  # Sorting
+
+  def sort(_, _, _), do: error()
+  def argsort(_, _, _), do: error()
+  def top_k(_, _), do: error()
+
+  # Linear Algebra
+
+  def dot(_, _, _, _, _, _), do: error()
+  def lu(_), do: error()
+  def qr(_, _), do: error()
+  def cholesky(_), do: error()
+  def triangular_solve(_, _, _, _, _), do: error()
+  def solve(_, _), do: error()
+  def svd(_, _), do: error()
+  def eigh(_, _, _), do: error()
+  def invert(_), do: error()
+  def determinant(_), do: error()
+
+  # Indexing
+
+  def slice(_, _, _, _), do: error()
+  def put_slice(_, _, _), do: error()
+  def pad(_, _, _), do: error()
+  def take(_, _, _), do: error()
+  def take_along_axis(_, _, _), do: error()
+  def gather(_, _), do: error()
+  def indexed_add(_, _, _), do: error()
+  def indexed_put(_, _, _), do: error()
+
+  # Convolution
+
+  def conv(_, _, _), do: error()
+
+  # FFT
+
+  def fft(_, _), do: error()
+  def ifft(_, _), do: error()
+
   # Shape
 
+  def broadcast(_, _, _), do: error()
   def reshape(_, _), do: error()
+  def transpose(_, _), do: error()
+  def concatenate(_, _, _), do: error()
 
   # Type
 
   def as_type(_, _), do: error()
 
+  # Random
+
+  def random_engine(_, _), do: error()
+  def random_engine_seed(_), do: error()
+  def random_engine_set_seed(_, _), do: error()
+  def random_uniform(_, _, _), do: error()
+  def random_normal(_, _, _), do: error()
+
   defp error, do: :erlang.nif_error(:nif_not_loaded)
 end
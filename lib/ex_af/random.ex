@@ -0,0 +1,133 @@
+defmodule ExAF.Random do
+  @moduledoc """
+  Random number generation on the device.
+
+  Numbers are generated by ArrayFire's random engines, which hold
+  their seed and state. Every call advances the state of the engine,
+  and engines of the same type and seed generate the same numbers,
+  so results can be reproduced by reseeding the engine:
+
+      engine = ExAF.Random.engine(seed: 42)
+      weights = ExAF.Random.normal(engine, {784, 128}, standard_deviation: 0.01)
+      mask = ExAF.Random.uniform(engine, {32, 128})
+
+  The generated tensors are allocated by `ExAF.Backend`.
+  """
+
+  alias ExAF.Native
+
+  import ExAF.Helpers
+
+  @engine_types [:philox, :threefry, :mersenne]
+
+  defmodule Engine do
+    @moduledoc """
+    A reference to an ArrayFire random engine.
+    """
+
+    defstruct [:resource]
+  end
+
+  @doc """
+  Creates a random engine.
+
+  ## Options
+
+    * `:type` - one of `:philox`, `:threefry` or `:mersenne`.
+      Defaults to `:philox`
+
+    * `:seed` - the seed of the engine. Defaults to `0`
+  """
+  def engine(opts \\ []) do
+    opts = Keyword.validate!(opts, type: :philox, seed: 0)
+    type = opts[:type]
+
+    unless type in @engine_types do
+      raise ArgumentError,
+            "expected :type to be one of #{inspect(@engine_types)}, got: #{inspect(type)}"
+    end
+
+    type
+    |> Atom.to_string()
+    |> Native.random_engine(opts[:seed])
+    |> unwrap!()
+  end
+
+  @doc """
+  Returns the seed of the engine.
+  """
+  def seed(%Engine{} = engine) do
+    engine
+    |> Native.random_engine_seed()
+    |> unwrap!()
+  end
+
+  @doc """
+  Reseeds the engine, which also resets its state.
+  """
+  def set_seed(%Engine{} = engine, seed) when is_integer(seed) and seed >= 0 do
+    engine
+    |> Native.random_engine_set_seed(seed)
+    |> unwrap!()
+  end
+
+  @doc """
+  Generates a tensor of numbers uniformly distributed in `[min, max)`.
+
+  ## Options
+
+    * `:type` - a float type. Defaults to `{:f, 32}`
+
+    * `:min` - the lower bound of the numbers. Defaults to `0.0`
+
+    * `:max` - the upper bound of the numbers. Defaults to `1.0`
+  """
+  def uniform(%Engine{} = engine, shape, opts \\ []) do
+    opts = Keyword.validate!(opts, type: {:f, 32}, min: 0.0, max: 1.0)
+
+    engine
+    |> generate(:random_uniform, shape, opts[:type])
+    |> scale(opts[:max] - opts[:min], opts[:min])
+  end
+
+  @doc """
+  Generates a tensor of normally distributed numbers.
+
+  ## Options
+
+    * `:type` - a float type. Defaults to `{:f, 32}`
+
+    * `:mean` - the mean of the distribution. Defaults to `0.0`
+
+    * `:standard_deviation` - the standard deviation of the
+      distribution. Defaults to `1.0`
+  """
+  def normal(%Engine{} = engine, shape, opts \\ []) do
+    opts = Keyword.validate!(opts, type: {:f, 32}, mean: 0.0, standard_deviation: 1.0)
+
+    engine
+    |> generate(:random_normal, shape, opts[:type])
+    |> scale(opts[:standard_deviation], opts[:mean])
+  end
+
+  defp generate(engine, op, shape, type) do
+    out = Nx.template(shape, Nx.Type.normalize!(type))
+
+    Native
+    |> apply(op, [engine, to_exaf_shape(out.shape), to_exaf_type(out.type)])
+    |> to_nx(out)
+  end
+
+  defp scale(tensor, factor, offset) when factor == 1 and offset == 0, do: tensor
+
+  # The scalars are allocated on ExAF too, the backend doesn't
+  # operate on tensors of other backends.
+  defp scale(tensor, factor, offset) do
+    factor = Nx.tensor(factor, type: tensor.type, backend: ExAF.Backend)
+    offset = Nx.tensor(offset, type: tensor.type, backend: ExAF.Backend)
+
+    tensor
+    |> Nx.multiply(factor)
+    |> Nx.add(offset)
+  end
+end
@@ -1,97 +1,141 @@
 use crate::datatypes::*;
+use crate::error::{catch_af, try_catch_af, ExAfError};
 
-use arrayfire::Dim4;
+use arrayfire::{
+    Array, BinaryOp, BorderType, Dim4, FloatingPoint, HasAfEnum, ImplicitPromote, Indexer, MatProp,
+    RealNumber, Seq,
+};
 use half::f16;
 use num_complex::{Complex32, Complex64};
 use rustler::types::{Binary, OwnedBinary};
-use rustler::{Atom, Env};
-use std::convert::TryInto;
+use rustler::{Atom, Env, NifMap, NifResult};
+use std::cell::Cell;
+use std::cmp::Ordering;
 
 mod atoms {
     rustler::atoms! {
-        ok
+        ok,
+        already_deallocated,
+        high
     }
 }
 
+// Joins arrays of the same dtype along a dim.
+macro_rules! join_arrays {
+    ($arrays:expr, $dim:expr, $($variant:ident),*) => {
+        match $arrays[0] {
+            $(ExAfArray::$variant(_) => $arrays
+                .iter()
+                .map(|array| match array {
+                    ExAfArray::$variant(a) => Ok(a),
+                    array => Err(mismatched_dtypes(&$arrays[0], array)),
+                })
+                .collect::<Result<Vec<_>, ExAfError>>()
+                .map(|arrays| join_chunks(arrays, $dim).to_exaf_array()),)*
+        }
+    };
+}
+
 // Public API
 
 // Backend management
 
 #[rustler::nif]
-pub fn backend_deallocate(array: ExAf) -> Atom {
-    let exaf_array = array.resource.value();
-
-    match exaf_array {
-        ExAfArray::U8(a) => drop(a),
-        ExAfArray::U16(a) => drop(a),
-        ExAfArray::U32(a) => drop(a),
-        ExAfArray::U64(a) => drop(a),
-        ExAfArray::S16(a) => drop(a),
-        ExAfArray::S32(a) => drop(a),
-        ExAfArray::S64(a) => drop(a),
-        ExAfArray::F16(a) => drop(a),
-        ExAfArray::F32(a) => drop(a),
-        ExAfArray::F64(a) => drop(a),
-        ExAfArray::C64(a) => drop(a),
-        ExAfArray::C128(a) => drop(a),
-    }
-
-    // ArrayFire has its own memory manager.
-    // So when we drop, the allocatted memory is marked as
-    // resusable rather than deleted.
+pub fn backend_deallocate(array: ExAf) -> NifResult<Atom> {
+    // Taking the array out of the resource drops the last reference
+    // to it, instead of waiting for the BEAM to garbage collect the
+    // resource.
     //
-    // This means that we can only return :ok because we don't
-    // really know if an array has been marked reusable or not.
-    //
-    // TODO: Document this behaviour (and the possible side effects
-    // of it) in the docs.
-
-    atoms::ok()
+    // ArrayFire has its own memory manager. So when we drop, the
+    // allocated memory is marked as reusable rather than deleted.
+    match array.resource.deallocate()? {
+        Some(exaf_array) => {
+            drop(exaf_array);
+            Ok(atoms::ok())
+        }
+        None => Ok(atoms::already_deallocated()),
+    }
 }
 
 // Creation
 
 #[rustler::nif]
-pub fn eye(shape: Vec<u64>, dtype: String) -> ExAf {
-    let shape = dim_from_shape(shape);
-    let dtype = dtype_from_string(dtype);
+pub fn eye(shape: Vec<u64>, dtype: String) -> NifResult<ExAf> {
+    let dims = dim_from_shape(&shape);
+    let dtype = dtype_from_string(dtype)?;
 
-    apply_generic_function_array!(identity, dtype, shape)
+    // Folding keeps the last two axes apart, the others are batched.
+    let result = catch_af("eye", &[], || {
+        apply_generic_function_array!(identity, dtype, dims)
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
 }
 
 #[rustler::nif]
-pub fn iota(shape: Vec<u64>, tdims: Vec<u64>, dtype: String) -> ExAf {
-    let shape = dim_from_shape(shape);
-    let dtype = dtype_from_string(dtype);
-    let tdims = dim_from_shape(tdims);
+pub fn iota(shape: Vec<u64>, axis: Option<usize>, dtype: String) -> NifResult<ExAf> {
+    let dims = dim_from_shape(&shape);
+    let dtype = dtype_from_string(dtype)?;
+
+    // Without an axis, counting through the dims in column-major order
+    // is counting through the shape in row-major order. With an axis,
+    // count along it and tile the sequence along all the other axes.
+    let (seq_dims, tile_dims) = match axis {
+        None => (dims, Dim4::new(&[1, 1, 1, 1])),
+        Some(axis) => {
+            let view = axis_view(&shape, axis);
+            let seq_dims = Dim4::new(&[1, view[1], 1, 1]);
+            let tile_dims = Dim4::new(&[view[0], 1, view[2], 1]);
+
+            (seq_dims, tile_dims)
+        }
+    };
+
+    let result = catch_af("iota", &[], || {
+        let result = apply_generic_function_array!(iota, dtype, seq_dims, tile_dims);
+        apply_function_array!(result, moddims, dims)
+    })?;
 
-    apply_generic_function_array!(iota, dtype, shape, tdims)
+    Ok(ExAf::from_exaf_array(result, shape))
 }
 
 // Conversion
 
 #[rustler::nif]
-pub fn from_binary(binary: Binary, shape: Vec<u64>, dtype: String) -> ExAf {
-    let dim = dim_from_shape(shape);
+pub fn from_binary(binary: Binary, shape: Vec<u64>, dtype: String) -> NifResult<ExAf> {
     let slice = binary.as_slice();
-    let dtype = dtype_from_string(dtype);
+    let dtype = dtype_from_string(dtype)?;
+
+    // ArrayFire reads as many elements as the dimensions hold, so a
+    // short binary would be read out of bounds.
+    let expected = shape.iter().product::<u64>() as usize * dtype.bytes();
+    if slice.len() != expected {
+        return Err(ExAfError::InvalidShape(format!(
+            "expected a binary of {} bytes but it was {}",
+            expected,
+            slice.len()
+        ))
+        .into());
+    }
 
-    ExAf::from_slice(slice, dim, dtype)
+    let result = catch_af("from_binary", &[], || ExAf::from_slice(slice, shape, dtype))?;
+
+    Ok(result)
 }
 
 #[rustler::nif]
-pub fn to_binary(env: Env, array: ExAf, limit: usize) -> Binary {
-    let exaf_array = array.resource.value();
+pub fn to_binary(env: Env, array: ExAf, limit: usize) -> NifResult<Binary> {
+    let exaf_array = array.resource.value()?;
 
-    let mut vec = exaf_array.to_vec();
+    let mut vec = catch_af("to_binary", &[&array], || exaf_array.to_vec())?;
     vec.truncate(exaf_array.dtype().bytes() * limit);
 
     let slice = vec.as_slice();
 
-    let mut erl_bin = OwnedBinary::new(vec.len()).unwrap();
+    let mut erl_bin = OwnedBinary::new(vec.len()).ok_or(ExAfError::BinaryAllocation)?;
     erl_bin.as_mut_slice().copy_from_slice(slice);
 
-    erl_bin.release(env)
+    Ok(erl_bin.release(env))
 }
 
 // Elementwise
@@ -99,21 +143,35 @@ pub fn to_binary(env: Env, array: ExAf, limit: usize) -> Binary {
 macro_rules! unary_op {
     ($op_name:ident, $af_op:ident) => {
         #[rustler::nif]
-        pub fn $op_name(array: ExAf) -> ExAf {
-            let ex_array = array.resource.value();
-            apply_function_array!(ex_array, $af_op,)
+        pub fn $op_name(array: ExAf) -> NifResult<ExAf> {
+            let ex_array = array.resource.value()?;
+
+            let result = catch_af(stringify!($op_name), &[&array], || {
+                apply_function_array!(ex_array, $af_op,)
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
         }
     };
 }
 
+// Ops that are only defined on some dtypes take a function that
+// checks the dtype of both operands.
 macro_rules! binary_op {
-    ($op_name:ident, $af_op:ident) => {
+    ($op_name:ident, $af_op:ident $(, $ensure_dtype:ident)?) => {
         #[rustler::nif]
-        pub fn $op_name(left: ExAf, right: ExAf) -> ExAf {
-            let left_array = left.resource.value();
-            let right_array = right.resource.value();
+        pub fn $op_name(left: ExAf, right: ExAf) -> NifResult<ExAf> {
+            let left_array = left.resource.value()?;
+            let right_array = right.resource.value()?;
+            $(
+                $ensure_dtype(&left_array)?;
+                $ensure_dtype(&right_array)?;
+            )?
 
-            match right_array {
+            // Both operands are broadcasted to the output shape
+            let inputs = [&left, &right];
+
+            let result = catch_af(stringify!($op_name), &inputs, || match right_array {
                 ExAfArray::U8(ref b) => apply_function_array!(left_array, $af_op, b, true),
                 ExAfArray::U16(ref b) => apply_function_array!(left_array, $af_op, b, true),
                 ExAfArray::U32(ref b) => apply_function_array!(left_array, $af_op, b, true),
@@ -126,7 +184,9 @@ macro_rules! binary_op {
                 ExAfArray::F64(ref b) => apply_function_array!(left_array, $af_op, b, true),
                 ExAfArray::C64(ref b) => apply_function_array!(left_array, $af_op, b, true),
                 ExAfArray::C128(ref b) => apply_function_array!(left_array, $af_op, b, true),
-            }
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, left.shape().to_vec()))
         }
     };
 }
@@ -168,6 +228,152 @@ binary_op!(logical_or, or);
 binary_op!(left_shift, shiftl);
 binary_op!(right_shift, shiftr);
 
+// Elementwise - Bitwise
+
+binary_op!(bitwise_and, bitand, ensure_integer);
+binary_op!(bitwise_or, bitor, ensure_integer);
+binary_op!(bitwise_xor, bitxor, ensure_integer);
+
+#[rustler::nif]
+pub fn bitwise_not(array: ExAf) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+
+    let result = try_catch_af("bitwise_not", &[&array], || match exaf_array {
+        ExAfArray::U8(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ExAfArray::U16(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ExAfArray::U32(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ExAfArray::U64(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ExAfArray::S16(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ExAfArray::S32(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ExAfArray::S64(ref a) => Ok(arrayfire::bitnot(a).to_exaf_array()),
+        ref array => Err(not_an_integer(array.dtype())),
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+}
+
+// ArrayFire has no bit counting functions, so bits are counted in
+// parallel within each integer (SWAR), which takes a few shifts and
+// masks per power of two in the width instead of one per bit.
+// Signed integers are counted as the unsigned integers with the
+// same bits, so that shifts don't extend their sign.
+macro_rules! bit_count_op {
+    ($op_name:ident, $count:ident) => {
+        #[rustler::nif]
+        pub fn $op_name(array: ExAf) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+
+            let result = try_catch_af(stringify!($op_name), &[&array], || match exaf_array {
+                ExAfArray::U8(ref a) => Ok($count(a, 8).to_exaf_array()),
+                ExAfArray::U16(ref a) => Ok($count(a, 16).to_exaf_array()),
+                ExAfArray::U32(ref a) => Ok($count(a, 32).to_exaf_array()),
+                ExAfArray::U64(ref a) => Ok($count(a, 64).to_exaf_array()),
+                ExAfArray::S16(ref a) => {
+                    Ok($count(&a.cast::<u16>(), 16).cast::<i16>().to_exaf_array())
+                }
+                ExAfArray::S32(ref a) => {
+                    Ok($count(&a.cast::<u32>(), 32).cast::<i32>().to_exaf_array())
+                }
+                ExAfArray::S64(ref a) => {
+                    Ok($count(&a.cast::<u64>(), 64).cast::<i64>().to_exaf_array())
+                }
+                ref array => Err(not_an_integer(array.dtype())),
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+        }
+    };
+}
+
+bit_count_op!(population_count, count_ones);
+bit_count_op!(count_leading_zeros, count_leading_zeros_of);
+
+fn count_ones<U>(array: &Array<U>, bits: u8) -> Array<U>
+where
+    U: HasAfEnum + ImplicitPromote<U, Output = U>,
+{
+    // Counts pairs of bits, then nibbles, then bytes, and then adds
+    // the bytes together.
+    let pairs = arrayfire::sub(
+        array,
+        &arrayfire::bitand(
+            &shift_right(array, 1),
+            &bit_pattern(0x5555_5555_5555_5555, bits, array.dims()),
+            false,
+        ),
+        false,
+    );
+    let nibble_mask = bit_pattern(0x3333_3333_3333_3333, bits, array.dims());
+    let nibbles = arrayfire::add(
+        &arrayfire::bitand(&pairs, &nibble_mask, false),
+        &arrayfire::bitand(&shift_right(&pairs, 2), &nibble_mask, false),
+        false,
+    );
+    let mut count = arrayfire::bitand(
+        &arrayfire::add(&nibbles, &shift_right(&nibbles, 4), false),
+        &bit_pattern(0x0F0F_0F0F_0F0F_0F0F, bits, array.dims()),
+        false,
+    );
+
+    let mut shift = 8;
+    while shift < bits {
+        count = arrayfire::add(&count, &shift_right(&count, shift), false);
+        shift *= 2;
+    }
+
+    arrayfire::bitand(&count, &bit_pattern(0xFF, bits, array.dims()), false)
+}
+
+fn count_leading_zeros_of<U>(array: &Array<U>, bits: u8) -> Array<U>
+where
+    U: HasAfEnum + ImplicitPromote<U, Output = U>,
+{
+    // Setting every bit below the highest set bit leaves the
+    // leading zeros as the only zeros.
+    let mut smeared = array.clone();
+    let mut shift = 1;
+    while shift < bits {
+        smeared = arrayfire::bitor(&smeared, &shift_right(&smeared, shift), false);
+        shift *= 2;
+    }
+
+    let width = arrayfire::constant(bits, array.dims()).cast::<U>();
+    arrayfire::sub(&width, &count_ones(&smeared, bits), false)
+}
+
+fn shift_right<U>(array: &Array<U>, shift: u8) -> Array<U>
+where
+    U: HasAfEnum + ImplicitPromote<U, Output = U>,
+{
+    let shift = arrayfire::constant(shift, array.dims()).cast::<U>();
+    arrayfire::shiftr(array, &shift, false)
+}
+
+// The pattern is truncated to the given width in bits.
+fn bit_pattern<U: HasAfEnum>(pattern: u64, bits: u8, dims: Dim4) -> Array<U> {
+    let mask = pattern & (u64::MAX >> (64 - bits as u32));
+
+    arrayfire::constant(mask, dims).cast::<U>()
+}
+
+fn ensure_integer(array: &ExAfArray) -> Result<(), ExAfError> {
+    match array.dtype() {
+        dtype @ (ExAfDType::F16
+        | ExAfDType::F32
+        | ExAfDType::F64
+        | ExAfDType::C64
+        | ExAfDType::C128) => Err(not_an_integer(dtype)),
+        _ => Ok(()),
+    }
+}
+
+fn not_an_integer(dtype: ExAfDType) -> ExAfError {
+    ExAfError::InvalidDType(format!(
+        "{}, bitwise operations are only defined on integers",
+        dtype.name()
+    ))
+}
+
 // Elementwise - Trignometry
 
 unary_op!(sin, sin);
@@ -205,82 +411,2901 @@ unary_op!(ceil, ceil);
 unary_op!(real, real);
 unary_op!(imag, imag);
 
-// Shape
+// Aggregates
 
-#[rustler::nif]
-pub fn reshape(array: ExAf, shape: Vec<u64>) -> ExAf {
-    let dim = dim_from_shape(shape);
-    let exaf_array = array.resource.value();
+macro_rules! reduction_op {
+    ($op_name:ident, $af_op:ident) => {
+        #[rustler::nif]
+        pub fn $op_name(array: ExAf, axes: Option<Vec<usize>>, keep_axes: bool) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+
+            let (result, shape) = catch_af(stringify!($op_name), &[&array], || {
+                reduce(&exaf_array, array.shape(), axes, keep_axes, |a, dim| {
+                    apply_function_array!(a, $af_op, dim)
+                })
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, shape))
+        }
+    };
+}
+
+reduction_op!(sum, sum);
+reduction_op!(product, product);
+reduction_op!(reduce_max, max);
+reduction_op!(reduce_min, min);
+reduction_op!(all, all_true);
+reduction_op!(any, any_true);
+
+/// Reduces the array over each of the axes in turn, or over the
+/// whole array at once when no axes are given.
+fn reduce<F>(
+    array: &ExAfArray,
+    shape: &[u64],
+    axes: Option<Vec<usize>>,
+    keep_axes: bool,
+    reducer: F,
+) -> (ExAfArray, Vec<u64>)
+where
+    F: Fn(&ExAfArray, i32) -> ExAfArray,
+{
+    let (reduced, axes) = match axes {
+        None => {
+            let size = shape.iter().product();
+            let flat = apply_function_array!(array, moddims, Dim4::new(&[size, 1, 1, 1]));
+
+            (reducer(&flat, 0), (0..shape.len()).collect())
+        }
+        Some(axes) => {
+            let mut reduced = array.clone();
+            let mut reduced_shape = shape.to_vec();
+
+            for axis in axes.iter() {
+                let view = axis_view(&reduced_shape, *axis);
+                let viewed = apply_function_array!(reduced, moddims, view);
 
-    apply_function_array!(exaf_array, moddims, dim)
+                reduced = reducer(&viewed, 1);
+                reduced_shape[*axis] = 1;
+            }
+
+            (reduced, axes)
+        }
+    };
+
+    let out_shape: Vec<u64> = shape
+        .iter()
+        .enumerate()
+        .filter_map(|(axis, size)| match (axes.contains(&axis), keep_axes) {
+            (false, _) => Some(*size),
+            (true, true) => Some(1),
+            (true, false) => None,
+        })
+        .collect();
+
+    let reduced = apply_function_array!(reduced, moddims, dim_from_shape(&out_shape));
+
+    (reduced, out_shape)
 }
 
-// Type
+macro_rules! arg_reduction_op {
+    ($op_name:ident, $af_op:ident) => {
+        #[rustler::nif]
+        pub fn $op_name(
+            array: ExAf,
+            axis: Option<usize>,
+            tie_break: Atom,
+            keep_axis: bool,
+        ) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+            let high = tie_break == atoms::high();
 
-#[rustler::nif]
-pub fn as_type(array: ExAf, dtype: String) -> ExAf {
-    let dtype = dtype_from_string(dtype);
-    let exaf_array = array.resource.value();
+            // There is no index to return along an empty axis.
+            let size: u64 = match axis {
+                Some(axis) => array.shape()[axis],
+                None => array.shape().iter().product(),
+            };
+            if size == 0 {
+                return Err(ExAfError::InvalidShape(format!(
+                    "can't take the {} of an empty axis",
+                    stringify!($op_name)
+                ))
+                .into());
+            }
+
+            let (result, shape) = catch_af(stringify!($op_name), &[&array], || {
+                arg_reduce(&exaf_array, array.shape(), axis, high, keep_axis, |a, dim| {
+                    match_array!(a, a => arrayfire::$af_op(a, dim).1)
+                })
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, shape))
+        }
+    };
+}
+
+arg_reduction_op!(argmax, imax);
+arg_reduction_op!(argmin, imin);
 
-    // Complex arrays don't support casting.
-    // So take their real part and then cast.
+/// Finds the index of the extreme along the axis, or along the
+/// flattened array when no axis is given.
+fn arg_reduce<F>(
+    array: &ExAfArray,
+    shape: &[u64],
+    axis: Option<usize>,
+    high: bool,
+    keep_axis: bool,
+    reducer: F,
+) -> (ExAfArray, Vec<u64>)
+where
+    F: Fn(&ExAfArray, i32) -> Array<u32>,
+{
+    let (view, out_shape) = match axis {
+        None => {
+            let size = shape.iter().product();
+            let out_shape = if keep_axis {
+                vec![1; shape.len()]
+            } else {
+                vec![]
+            };
 
-    // TODO: Use the local real function instead
+            (Dim4::new(&[1, size, 1, 1]), out_shape)
+        }
+        Some(axis) => {
+            let mut out_shape = shape.to_vec();
+            if keep_axis {
+                out_shape[axis] = 1;
+            } else {
+                out_shape.remove(axis);
+            }
 
-    let exaf_array = match exaf_array {
-        ExAfArray::C64(ref a) => ExAfArray::F32(arrayfire::real(a)),
-        ExAfArray::C128(ref a) => ExAfArray::F64(arrayfire::real(a)),
-        _ => exaf_array,
+            (axis_view(shape, axis), out_shape)
+        }
     };
 
-    let new_exaf_array = match dtype {
-        ExAfDType::U8 => ExAfArray::U8(apply_generic_method_array!(exaf_array, cast, u8,)),
-        ExAfDType::U16 => ExAfArray::U16(apply_generic_method_array!(exaf_array, cast, u16,)),
-        ExAfDType::U32 => ExAfArray::U32(apply_generic_method_array!(exaf_array, cast, u32,)),
-        ExAfDType::U64 => ExAfArray::U64(apply_generic_method_array!(exaf_array, cast, u64,)),
-        ExAfDType::S16 => ExAfArray::S16(apply_generic_method_array!(exaf_array, cast, i16,)),
-        ExAfDType::S32 => ExAfArray::S32(apply_generic_method_array!(exaf_array, cast, i32,)),
-        ExAfDType::S64 => ExAfArray::S64(apply_generic_method_array!(exaf_array, cast, i64,)),
-        ExAfDType::F16 => ExAfArray::F16(apply_generic_method_array!(exaf_array, cast, f16,)),
-        ExAfDType::F32 => ExAfArray::F32(apply_generic_method_array!(exaf_array, cast, f32,)),
-        ExAfDType::F64 => ExAfArray::F64(apply_generic_method_array!(exaf_array, cast, f64,)),
-        ExAfDType::C64 => ExAfArray::C64(apply_generic_method_array!(exaf_array, cast, Complex32,)),
-        ExAfDType::C128 => {
-            ExAfArray::C128(apply_generic_method_array!(exaf_array, cast, Complex64,))
+    let viewed = apply_function_array!(array, moddims, view);
+
+    // ArrayFire returns the first index on ties, so the last one is
+    // found by searching the reversed axis.
+    let indices = if high {
+        let flipped = map_array!(viewed, a => arrayfire::flip(a, 1));
+        let indices = reducer(&flipped, 1);
+        let last = arrayfire::constant(view[1] as u32 - 1, indices.dims());
+
+        arrayfire::sub(&last, &indices, false)
+    } else {
+        reducer(&viewed, 1)
+    };
+
+    let indices = arrayfire::moddims(&indices, dim_from_shape(&out_shape));
+
+    (indices.to_exaf_array(), out_shape)
+}
+
+// Aggregates - Cumulative
+
+macro_rules! cumulative_op {
+    ($op_name:ident, $binary_op:expr) => {
+        #[rustler::nif]
+        pub fn $op_name(array: ExAf, axis: usize, reverse: bool) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+
+            let result = catch_af(stringify!($op_name), &[&array], || {
+                cumulative(&exaf_array, array.shape(), axis, reverse, $binary_op)
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+        }
+    };
+}
+
+cumulative_op!(cumulative_sum, BinaryOp::ADD);
+cumulative_op!(cumulative_product, BinaryOp::MUL);
+cumulative_op!(cumulative_max, BinaryOp::MAX);
+cumulative_op!(cumulative_min, BinaryOp::MIN);
+
+/// Scans the array along the axis, starting from its end when
+/// reversed.
+fn cumulative(
+    array: &ExAfArray,
+    shape: &[u64],
+    axis: usize,
+    reverse: bool,
+    op: BinaryOp,
+) -> ExAfArray {
+    let viewed = apply_function_array!(array, moddims, axis_view(shape, axis));
+    let flip = |a: ExAfArray| {
+        if reverse {
+            map_array!(a, a => arrayfire::flip(a, 1))
+        } else {
+            a
         }
     };
 
-    ExAf::from_exaf_array(new_exaf_array)
+    let scanned = map_array!(flip(viewed), a => arrayfire::scan(a, 1, op, true));
+    let scanned = flip(scanned);
+
+    // Small types are scanned into a wider type, which would no
+    // longer overflow the way Nx does.
+    let scanned = if scanned.dtype() != array.dtype() {
+        cast(&scanned, array.dtype())
+    } else {
+        scanned
+    };
+
+    apply_function_array!(scanned, moddims, dim_from_shape(shape))
 }
 
-// Helpers
+// Aggregates - Windows
 
-fn dim_from_shape(shape: Vec<u64>) -> Dim4 {
-    let array: [u64; 4] = match shape.as_slice().try_into() {
-        Ok(ba) => ba,
-        Err(_) => panic!(
-            "Expected a shape of length {} but it was {}",
-            32,
-            shape.len()
-        ),
+#[derive(Clone, Copy)]
+enum WindowOp {
+    Sum,
+    Product,
+    Max,
+    Min,
+}
+
+impl WindowOp {
+    /// The value windows are padded with, which never changes the
+    /// result of the reduction.
+    fn identity(self, dtype: ExAfDType) -> ExAfArray {
+        let dims = Dim4::new(&[1, 1, 1, 1]);
+
+        macro_rules! bound {
+            ($lowest:expr, $highest:expr) => {
+                match self {
+                    WindowOp::Max => arrayfire::constant($lowest, dims).to_exaf_array(),
+                    _ => arrayfire::constant($highest, dims).to_exaf_array(),
+                }
+            };
+        }
+
+        match self {
+            WindowOp::Sum => cast(&arrayfire::constant(0u8, dims).to_exaf_array(), dtype),
+            WindowOp::Product => cast(&arrayfire::constant(1u8, dims).to_exaf_array(), dtype),
+            WindowOp::Max | WindowOp::Min => match dtype {
+                ExAfDType::U8 => bound!(u8::MIN, u8::MAX),
+                ExAfDType::U16 => bound!(u16::MIN, u16::MAX),
+                ExAfDType::U32 => bound!(u32::MIN, u32::MAX),
+                ExAfDType::U64 => bound!(u64::MIN, u64::MAX),
+                ExAfDType::S16 => bound!(i16::MIN, i16::MAX),
+                ExAfDType::S32 => bound!(i32::MIN, i32::MAX),
+                ExAfDType::S64 => bound!(i64::MIN, i64::MAX),
+                ExAfDType::F16 => bound!(f16::NEG_INFINITY, f16::INFINITY),
+                ExAfDType::F32 | ExAfDType::C64 => bound!(f32::NEG_INFINITY, f32::INFINITY),
+                ExAfDType::F64 | ExAfDType::C128 => bound!(f64::NEG_INFINITY, f64::INFINITY),
+            },
+        }
+    }
+
+    fn combine(self, left: &ExAfArray, right: &ExAfArray) -> Result<ExAfArray, ExAfError> {
+        match self {
+            WindowOp::Sum => map_array_pair!(left, right, (l, r) => arrayfire::add(l, r, false)),
+            WindowOp::Product => {
+                map_array_pair!(left, right, (l, r) => arrayfire::mul(l, r, false))
+            }
+            WindowOp::Max => map_array_pair!(left, right, (l, r) => arrayfire::maxof(l, r, false)),
+            WindowOp::Min => map_array_pair!(left, right, (l, r) => arrayfire::minof(l, r, false)),
+        }
+    }
+
+    fn reduce(self, array: &ExAfArray, dim: i32) -> ExAfArray {
+        match self {
+            WindowOp::Sum => apply_function_array!(array, sum, dim),
+            WindowOp::Product => apply_function_array!(array, product, dim),
+            WindowOp::Max => apply_function_array!(array, max, dim),
+            WindowOp::Min => apply_function_array!(array, min, dim),
+        }
+    }
+}
+
+macro_rules! window_op {
+    ($op_name:ident, $window_op:expr) => {
+        #[rustler::nif]
+        pub fn $op_name(
+            array: ExAf,
+            window_dimensions: Vec<u64>,
+            strides: Vec<u64>,
+            padding: Vec<(i64, i64)>,
+            window_dilations: Vec<u64>,
+        ) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+
+            let (result, shape) = try_catch_af(stringify!($op_name), &[&array], || {
+                window_reduce(
+                    $window_op,
+                    &exaf_array,
+                    array.shape(),
+                    &window_dimensions,
+                    &strides,
+                    &padding,
+                    &window_dilations,
+                )
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, shape))
+        }
+    };
+}
+
+window_op!(window_sum, WindowOp::Sum);
+window_op!(window_product, WindowOp::Product);
+window_op!(window_max, WindowOp::Max);
+window_op!(window_min, WindowOp::Min);
+
+fn window_reduce(
+    op: WindowOp,
+    array: &ExAfArray,
+    shape: &[u64],
+    window_dimensions: &[u64],
+    strides: &[u64],
+    padding: &[(i64, i64)],
+    window_dilations: &[u64],
+) -> Result<(ExAfArray, Vec<u64>), ExAfError> {
+    let dtype = array.dtype();
+    let config: Vec<(i64, i64, u64)> = padding.iter().map(|&(low, high)| (low, high, 0)).collect();
+    let (padded, padded_shape) = pad_array(array, shape, &op.identity(dtype), &config)?;
+
+    let out_shape: Vec<u64> = (0..shape.len())
+        .map(|axis| {
+            let window = (window_dimensions[axis] - 1) * window_dilations[axis] + 1;
+            (padded_shape[axis] - window) / strides[axis] + 1
+        })
+        .collect();
+
+    let rank = shape.len();
+    let leading = rank.saturating_sub(2);
+    let unwrappable = rank <= 4
+        && window_dilations.iter().all(|&dilation| dilation == 1)
+        && window_dimensions[..leading]
+            .iter()
+            .all(|&window| window == 1)
+        && strides[..leading].iter().all(|&stride| stride == 1);
+
+    let result = if unwrappable {
+        // Unwrap every window of the two innermost axes into a
+        // column and reduce the columns.
+        let window = |axis: usize| {
+            if axis < rank {
+                window_dimensions[rank - 1 - axis]
+            } else {
+                1
+            }
+        };
+        let stride = |axis: usize| {
+            if axis < rank {
+                strides[rank - 1 - axis]
+            } else {
+                1
+            }
+        };
+
+        let columns = map_array!(padded, a => {
+            arrayfire::unwrap(a, window(0) as i64, window(1) as i64, stride(0) as i64, stride(1) as i64, 0, 0, true)
+        });
+        let reduced = op.reduce(&columns, 0);
+        let reduced = if reduced.dtype() == dtype {
+            reduced
+        } else {
+            cast(&reduced, dtype)
+        };
+
+        apply_function_array!(reduced, moddims, dim_from_shape(&out_shape))
+    } else {
+        // Combine one strided slice per position in the window.
+        let mut result: Option<ExAfArray> = None;
+
+        for position in window_positions(window_dimensions) {
+            let start_indices: Vec<u64> = (0..rank)
+                .map(|axis| position[axis] * window_dilations[axis])
+                .collect();
+            let slice = slice_array(&padded, &padded_shape, &start_indices, &out_shape, strides);
+
+            result = Some(match result {
+                Some(result) => op.combine(&result, &slice)?,
+                None => slice,
+            });
+        }
+
+        result.unwrap_or(padded)
+    };
+
+    Ok((result, out_shape))
+}
+
+macro_rules! window_scatter_op {
+    ($op_name:ident, $window_op:expr) => {
+        #[rustler::nif]
+        pub fn $op_name(
+            array: ExAf,
+            source: ExAf,
+            init_value: ExAf,
+            window_dimensions: Vec<u64>,
+            strides: Vec<u64>,
+            padding: Vec<(i64, i64)>,
+        ) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+            let exaf_source = source.resource.value()?;
+            let exaf_init_value = init_value.resource.value()?;
+
+            let result = try_catch_af(
+                stringify!($op_name),
+                &[&array, &source, &init_value],
+                || {
+                    window_scatter(
+                        $window_op,
+                        &exaf_array,
+                        array.shape(),
+                        &exaf_source,
+                        &exaf_init_value,
+                        &window_dimensions,
+                        &strides,
+                        &padding,
+                    )
+                },
+            )?;
+
+            Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+        }
+    };
+}
+
+window_scatter_op!(window_scatter_max, WindowOp::Max);
+window_scatter_op!(window_scatter_min, WindowOp::Min);
+
+/// Adds every source value to the element its window selected,
+/// the first maximum or minimum in the window.
+#[allow(clippy::too_many_arguments)]
+fn window_scatter(
+    op: WindowOp,
+    array: &ExAfArray,
+    shape: &[u64],
+    source: &ExAfArray,
+    init_value: &ExAfArray,
+    window_dimensions: &[u64],
+    strides: &[u64],
+    padding: &[(i64, i64)],
+) -> Result<ExAfArray, ExAfError> {
+    let dtype = array.dtype();
+    let rank = shape.len();
+
+    let config: Vec<(i64, i64, u64)> = padding.iter().map(|&(low, high)| (low, high, 0)).collect();
+    let (padded, padded_shape) = pad_array(array, shape, &op.identity(dtype), &config)?;
+
+    let out_shape: Vec<u64> = (0..rank)
+        .map(|axis| (padded_shape[axis] - window_dimensions[axis]) / strides[axis] + 1)
+        .collect();
+    let out_dims = dim_from_shape(&out_shape);
+
+    let positions: Vec<Vec<u64>> = window_positions(window_dimensions);
+
+    // Find the position of the selected element in every window.
+    let mut best = slice_array(&padded, &padded_shape, &positions[0], &out_shape, strides);
+    let mut best_position = arrayfire::constant(0u32, out_dims);
+
+    for (index, start_indices) in positions.iter().enumerate().skip(1) {
+        let slice = slice_array(&padded, &padded_shape, start_indices, &out_shape, strides);
+
+        let better = match_array_pair!(&slice, &best, (a, b) => match op {
+            WindowOp::Max => arrayfire::gt(a, b, false),
+            _ => arrayfire::lt(a, b, false),
+        })?;
+
+        best = map_array_pair!(&slice, &best, (a, b) => arrayfire::select(a, &better, b))?;
+        best_position = arrayfire::select(
+            &arrayfire::constant(index as u32, out_dims),
+            &better,
+            &best_position,
+        );
+    }
+
+    // Scatter the source one window position at a time, so that
+    // the strided slices written to never overlap.
+    let zeros = map_array!(WindowOp::Sum.identity(dtype), a => arrayfire::tile(a, out_dims));
+    let padded_dims = dim_from_shape(&padded_shape);
+    let mut scattered =
+        map_array!(WindowOp::Sum.identity(dtype), a => arrayfire::tile(a, padded_dims));
+
+    for (index, start_indices) in positions.iter().enumerate() {
+        let selected = arrayfire::eq(&best_position, &(index as u32), false);
+        let updates =
+            map_array_pair!(source, &zeros, (s, z) => arrayfire::select(s, &selected, z))?;
+
+        let current = slice_array(
+            &scattered,
+            &padded_shape,
+            start_indices,
+            &out_shape,
+            strides,
+        );
+        let updated = map_array_pair!(&current, &updates, (c, u) => arrayfire::add(c, u, false))?;
+
+        scattered = assign_slice(
+            &scattered,
+            &padded_shape,
+            start_indices,
+            &updated,
+            &out_shape,
+            strides,
+        )?;
+    }
+
+    let crop_indices: Vec<u64> = padding.iter().map(|&(low, _)| low.max(0) as u64).collect();
+    let cropped = slice_array(
+        &scattered,
+        &padded_shape,
+        &crop_indices,
+        shape,
+        &vec![1; rank],
+    );
+
+    let dims = dim_from_shape(shape);
+    let init = map_array!(init_value, a => arrayfire::tile(a, dims));
+
+    map_array_pair!(&cropped, &init, (c, i) => arrayfire::add(c, i, false))
+}
+
+/// Every position within a window, in row-major order.
+fn window_positions(window_dimensions: &[u64]) -> Vec<Vec<u64>> {
+    let count: u64 = window_dimensions.iter().product();
+    let mut position = vec![0; window_dimensions.len()];
+    let mut positions = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        positions.push(position.clone());
+
+        for axis in (0..position.len()).rev() {
+            position[axis] += 1;
+            if position[axis] < window_dimensions[axis] {
+                break;
+            }
+            position[axis] = 0;
+        }
+    }
+
+    positions
+}
+
+// Sorting
+
+#[rustler::nif]
+pub fn sort(array: ExAf, axis: usize, descending: bool) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    ensure_sortable(&exaf_array)?;
+
+    let result = try_catch_af("sort", &[&array], || {
+        let (columns, columns_shape) = sort_columns(&exaf_array, array.shape(), axis);
+        let order = stable_order(&columns, descending)?;
+        let sorted = map_array!(columns, a => take_columns(a, &order));
+
+        Ok(unsort_columns(&sorted, &columns_shape))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+}
+
+#[rustler::nif]
+pub fn argsort(array: ExAf, axis: usize, descending: bool) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    ensure_sortable(&exaf_array)?;
+
+    let result = try_catch_af("argsort", &[&array], || {
+        let (columns, columns_shape) = sort_columns(&exaf_array, array.shape(), axis);
+        let order = stable_order(&columns, descending)?;
+
+        Ok(unsort_columns(&order.to_exaf_array(), &columns_shape))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+}
+
+fn ensure_sortable(array: &ExAfArray) -> Result<(), ExAfError> {
+    match array.dtype() {
+        dtype @ (ExAfDType::C64 | ExAfDType::C128) => Err(not_sortable(dtype)),
+        _ => Ok(()),
+    }
+}
+
+fn not_sortable(dtype: ExAfDType) -> ExAfError {
+    ExAfError::InvalidDType(format!("{}, complex numbers can't be sorted", dtype.name()))
+}
+
+/// Moves the sorted axis to dim 0 and every other axis to dim 1,
+/// since ArrayFire sorts along dim 0. Returns the logical shape
+/// the columns were permuted from.
+fn sort_columns(array: &ExAfArray, shape: &[u64], axis: usize) -> (ExAfArray, Vec<u64>) {
+    let size = shape[axis];
+    let outer = shape[..axis].iter().product();
+    let inner = shape[axis + 1..].iter().product();
+
+    let permuted = permute(array, &[outer, size, inner], &[0, 2, 1]);
+    let columns = Dim4::new(&[size, outer * inner, 1, 1]);
+
+    (
+        apply_function_array!(permuted, moddims, columns),
+        vec![outer, inner, size],
+    )
+}
+
+fn unsort_columns(columns: &ExAfArray, columns_shape: &[u64]) -> ExAfArray {
+    permute(columns, columns_shape, &[0, 2, 1])
+}
+
+/// Finds the order of every column, breaking ties by position so
+/// that the sort is stable. NaNs go after every other value, the
+/// way Nx.BinaryBackend sorts them.
+fn stable_order(columns: &ExAfArray, descending: bool) -> Result<Array<u32>, ExAfError> {
+    match columns {
+        ExAfArray::U8(a) => Ok(column_order(a, descending)),
+        ExAfArray::U16(a) => Ok(column_order(a, descending)),
+        ExAfArray::U32(a) => Ok(column_order(a, descending)),
+        ExAfArray::U64(a) => Ok(column_order(a, descending)),
+        ExAfArray::S16(a) => Ok(column_order(a, descending)),
+        ExAfArray::S32(a) => Ok(column_order(a, descending)),
+        ExAfArray::S64(a) => Ok(column_order(a, descending)),
+        ExAfArray::F16(a) => Ok(column_order(&a.cast::<f32>(), descending)),
+        ExAfArray::F32(a) => Ok(column_order(a, descending)),
+        ExAfArray::F64(a) => Ok(column_order(a, descending)),
+        columns => Err(not_sortable(columns.dtype())),
+    }
+}
+
+// ArrayFire's sort is not guaranteed to be stable, and places NaNs
+// anywhere. So values are sorted once to rank them, and then the
+// positions are sorted by rank and original position, which are
+// unique keys.
+fn column_order<T>(columns: &Array<T>, descending: bool) -> Array<u32>
+where
+    T: HasAfEnum + RealNumber + ImplicitPromote<T>,
+{
+    let dims = columns.dims();
+    let (size, count) = (dims[0], dims[1]);
+    let positions =
+        arrayfire::iota::<u32>(Dim4::new(&[size, 1, 1, 1]), Dim4::new(&[1, count, 1, 1]));
+
+    // NaNs rank as infinity at first, they are moved after it below.
+    let not_nan = arrayfire::eq(columns, columns, false);
+    let mut keys = columns.clone();
+    arrayfire::replace_scalar(&mut keys, &not_nan, f64::INFINITY);
+
+    let (sorted, order) = arrayfire::sort_by_key(&keys, &positions, 0, true);
+
+    // A new rank starts wherever the sorted value changes.
+    let changed = if size > 1 {
+        let previous = arrayfire::rows(&sorted, 0, size as i64 - 2);
+        let next = arrayfire::rows(&sorted, 1, size as i64 - 1);
+        let changed = arrayfire::neq(&next, &previous, false).cast::<u64>();
+
+        arrayfire::join(
+            0,
+            &arrayfire::constant(0u64, Dim4::new(&[1, count, 1, 1])),
+            &changed,
+        )
+    } else {
+        arrayfire::constant(0u64, dims)
+    };
+    let runs = arrayfire::accum(&changed, 0);
+
+    // Every run gets two ranks, the second one for its NaNs.
+    let nans = arrayfire::sub(
+        &arrayfire::constant(1u64, dims),
+        &take_columns(&not_nan.cast::<u64>(), &order),
+        false,
+    );
+    let ranks = arrayfire::add(
+        &arrayfire::mul(&runs, &arrayfire::constant(2u64, dims), false),
+        &nans,
+        false,
+    );
+    let ranks = if descending {
+        arrayfire::sub(&arrayfire::constant(2 * size, dims), &ranks, false)
+    } else {
+        ranks
     };
-    Dim4::new(&array)
+
+    let keys = arrayfire::add(
+        &arrayfire::mul(&ranks, &arrayfire::constant(size, dims), false),
+        &order.cast::<u64>(),
+        false,
+    );
+
+    arrayfire::sort_by_key(&keys, &order, 0, true).1
+}
+
+/// Takes the given positions from every column, which may be
+/// fewer than the column size.
+fn take_columns<T: HasAfEnum>(columns: &Array<T>, positions: &Array<u32>) -> Array<T> {
+    let size = columns.dims()[0];
+    let dims = positions.dims();
+    let offsets = arrayfire::iota::<u32>(
+        Dim4::new(&[1, dims[1], 1, 1]),
+        Dim4::new(&[dims[0], 1, 1, 1]),
+    );
+    let indices = arrayfire::add(
+        positions,
+        &arrayfire::mul(&offsets, &arrayfire::constant(size as u32, dims), false),
+        false,
+    );
+
+    let taken = arrayfire::lookup(&arrayfire::flat(columns), &arrayfire::flat(&indices), 0);
+
+    arrayfire::moddims(&taken, dims)
+}
+
+#[rustler::nif]
+pub fn top_k(array: ExAf, k: u64) -> NifResult<(ExAf, ExAf)> {
+    let exaf_array = array.resource.value()?;
+    ensure_sortable(&exaf_array)?;
+
+    let shape = array.shape();
+    let size = shape[shape.len() - 1];
+    let mut out_shape = shape.to_vec();
+    out_shape[shape.len() - 1] = k;
+
+    let (values, indices) = try_catch_af("top_k", &[&array], || {
+        // The last axis is already ArrayFire's dim 0.
+        let columns = Dim4::new(&[size, shape.iter().product::<u64>() / size, 1, 1]);
+        let columns = apply_function_array!(exaf_array, moddims, columns);
+
+        let native = match columns {
+            ExAfArray::S32(ref a) if k <= TOPK_MAX_K => native_top_k(a, k),
+            ExAfArray::U32(ref a) if k <= TOPK_MAX_K => native_top_k(a, k),
+            ExAfArray::F32(ref a) if k <= TOPK_MAX_K => native_top_k(a, k),
+            ExAfArray::F64(ref a) if k <= TOPK_MAX_K => native_top_k(a, k),
+            _ => None,
+        };
+
+        let (values, indices) = match native {
+            Some(top_k) => top_k,
+            None => {
+                let order = stable_order(&columns, true)?;
+                let indices = arrayfire::rows(&order, 0, k as i64 - 1);
+
+                (map_array!(columns, a => take_columns(a, &indices)), indices)
+            }
+        };
+
+        let out_dims = dim_from_shape(&out_shape);
+
+        Ok((
+            apply_function_array!(values, moddims, out_dims),
+            arrayfire::moddims(&indices, out_dims).to_exaf_array(),
+        ))
+    })?;
+
+    Ok((
+        ExAf::from_exaf_array(values, out_shape.clone()),
+        ExAf::from_exaf_array(indices, out_shape),
+    ))
 }
 
-fn dtype_from_string(dtype: String) -> ExAfDType {
+// The largest k ArrayFire's topk supports on every device.
+const TOPK_MAX_K: u64 = 256;
+
+// ArrayFire's topk only supports a few dtypes. It also picks any of
+// the values tied with the k-th largest one and places NaNs
+// anywhere, so its result is only used when the k largest values
+// of every column are unambiguous. They are then ordered by value
+// and position, like the sort fallback orders them.
+fn native_top_k<T>(columns: &Array<T>, k: u64) -> Option<(ExAfArray, Array<u32>)>
+where
+    T: HasAfEnum + RealNumber + ImplicitPromote<T>,
+    Array<T>: ArrayToExAfArray,
+{
+    if arrayfire::any_true_all(&arrayfire::neq(columns, columns, false)).0 {
+        return None;
+    }
+
+    let (values, indices) = arrayfire::topk(columns, k as u32, 0, arrayfire::TopkFn::MAX);
+
+    // Values left out of the top k may be tied with the k-th one.
+    let size = columns.dims()[0];
+    let kth = arrayfire::tile(
+        &arrayfire::row(&values, k as i64 - 1),
+        Dim4::new(&[size, 1, 1, 1]),
+    );
+    let counts = arrayfire::sum(&arrayfire::ge(columns, &kth, false).cast::<u32>(), 0);
+
+    if arrayfire::any_true_all(&arrayfire::gt(&counts, &(k as u32), false)).0 {
+        return None;
+    }
+
+    let (indices, values) = arrayfire::sort_by_key(&indices, &values, 0, true);
+    let order = column_order(&values, true);
+
+    Some((
+        take_columns(&values, &order).to_exaf_array(),
+        take_columns(&indices, &order),
+    ))
+}
+
+// Linear Algebra
+
+#[rustler::nif]
+pub fn dot(
+    left: ExAf,
+    contract_axes1: Vec<usize>,
+    batch_axes1: Vec<usize>,
+    right: ExAf,
+    contract_axes2: Vec<usize>,
+    batch_axes2: Vec<usize>,
+) -> NifResult<ExAf> {
+    let left_array = left.resource.value()?;
+    let right_array = right.resource.value()?;
+
+    let left_operand = DotOperand::new(left.shape(), &contract_axes1, &batch_axes1, true);
+    let right_operand = DotOperand::new(right.shape(), &contract_axes2, &batch_axes2, false);
+
+    let mut shape: Vec<u64> = batch_axes1.iter().map(|&axis| left.shape()[axis]).collect();
+    shape.extend(&left_operand.free_shape);
+    shape.extend(&right_operand.free_shape);
+
+    let result = try_catch_af("dot", &[&left, &right], || {
+        let dtype = left_array.dtype();
+        let compute_dtype = dot_dtype(dtype);
+
+        let left_matrix = left_operand.to_matrix(&left_array, left.shape(), compute_dtype);
+        let right_matrix = right_operand.to_matrix(&right_array, right.shape(), compute_dtype);
+
+        // ArrayFire sees the transpose of every row-major matrix, so
+        // (L R)^T is computed as R^T L^T.
+        let product = matmul(
+            &right_matrix,
+            &left_matrix,
+            right_operand.mat_prop(),
+            left_operand.mat_prop(),
+        )?;
+        let product = apply_function_array!(product, moddims, dim_from_shape(&shape));
+
+        if compute_dtype == dtype {
+            Ok(product)
+        } else {
+            Ok(cast(&product, dtype))
+        }
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+/// An operand of dot as a batch of row-major matrices, with its
+/// batch axes first followed by its free and contracted axes in
+/// either order.
+struct DotOperand {
+    axes: Vec<usize>,
+    free_shape: Vec<u64>,
+    batch: u64,
+    free: u64,
+    contract: u64,
+    contract_last: bool,
+    left: bool,
+}
+
+impl DotOperand {
+    fn new(shape: &[u64], contract_axes: &[usize], batch_axes: &[usize], left: bool) -> Self {
+        let free_axes: Vec<usize> = (0..shape.len())
+            .filter(|axis| !contract_axes.contains(axis) && !batch_axes.contains(axis))
+            .collect();
+
+        let size = |axes: &[usize]| axes.iter().map(|&axis| shape[axis]).product::<u64>();
+        let in_order = |axes: &[usize]| axes.iter().enumerate().all(|(i, &axis)| i == axis);
+
+        let contract_last_axes = [batch_axes, &free_axes[..], contract_axes].concat();
+        let contract_first_axes = [batch_axes, contract_axes, &free_axes[..]].concat();
+
+        // Prefer whichever order the operand is already in, matmul
+        // can transpose it without moving any data.
+        let contract_last = if in_order(&contract_last_axes) {
+            true
+        } else if in_order(&contract_first_axes) {
+            false
+        } else {
+            left
+        };
+
+        DotOperand {
+            axes: if contract_last {
+                contract_last_axes
+            } else {
+                contract_first_axes
+            },
+            free_shape: free_axes.iter().map(|&axis| shape[axis]).collect(),
+            batch: size(batch_axes),
+            free: size(&free_axes),
+            contract: size(contract_axes),
+            contract_last,
+            left,
+        }
+    }
+
+    /// Lays the operand out as [columns, rows, batch] in ArrayFire.
+    fn to_matrix(&self, array: &ExAfArray, shape: &[u64], dtype: ExAfDType) -> ExAfArray {
+        let array = if array.dtype() == dtype {
+            array.clone()
+        } else {
+            cast(array, dtype)
+        };
+
+        let permuted = permute(&array, shape, &self.axes);
+
+        let dims = if self.contract_last {
+            [self.contract, self.free, self.batch, 1]
+        } else {
+            [self.free, self.contract, self.batch, 1]
+        };
+
+        apply_function_array!(permuted, moddims, Dim4::new(&dims))
+    }
+
+    /// ArrayFire reads the left operand as L^T when its contracted
+    /// axes come last and the right operand as R^T when they come
+    /// first, otherwise matmul has to transpose them.
+    fn mat_prop(&self) -> MatProp {
+        if self.contract_last == self.left {
+            MatProp::NONE
+        } else {
+            MatProp::TRANS
+        }
+    }
+}
+
+// BLAS only supports floating point and complex matrices.
+fn matmul_dtype(dtype: ExAfDType) -> ExAfDType {
+    match dtype {
+        ExAfDType::F32 | ExAfDType::F64 | ExAfDType::C64 | ExAfDType::C128 => dtype,
+        ExAfDType::F16 => ExAfDType::F32,
+        _ => ExAfDType::F64,
+    }
+}
+
+// Integers are multiplied in their own dtype, f64 can't hold
+// every s64 and u64 product exactly.
+fn dot_dtype(dtype: ExAfDType) -> ExAfDType {
+    match dtype {
+        ExAfDType::F16 => ExAfDType::F32,
+        _ => dtype,
+    }
+}
+
+fn matmul(
+    left: &ExAfArray,
+    right: &ExAfArray,
+    left_prop: MatProp,
+    right_prop: MatProp,
+) -> Result<ExAfArray, ExAfError> {
+    match (left, right) {
+        (ExAfArray::F32(l), ExAfArray::F32(r)) => {
+            Ok(arrayfire::matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::F64(l), ExAfArray::F64(r)) => {
+            Ok(arrayfire::matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::C64(l), ExAfArray::C64(r)) => {
+            Ok(arrayfire::matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::C128(l), ExAfArray::C128(r)) => {
+            Ok(arrayfire::matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::U8(l), ExAfArray::U8(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::U16(l), ExAfArray::U16(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::U32(l), ExAfArray::U32(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::U64(l), ExAfArray::U64(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::S16(l), ExAfArray::S16(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::S32(l), ExAfArray::S32(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (ExAfArray::S64(l), ExAfArray::S64(r)) => {
+            Ok(integer_matmul(l, r, left_prop, right_prop).to_exaf_array())
+        }
+        (left, right) if left.dtype() == right.dtype() => Err(ExAfError::InvalidDType(format!(
+            "{}, matrices are multiplied in f32, f64 or integers",
+            left.dtype().name()
+        ))),
+        (left, right) => Err(mismatched_dtypes(left, right)),
+    }
+}
+
+// The most products integer_matmul computes at once.
+const INTEGER_MATMUL_CHUNK: u64 = 1 << 24;
+
+/// Multiplies batches of integer matrices exactly, as a sum of
+/// elementwise products, which wraps on overflow like Nx does.
+/// The contracted dim is split in chunks to bound the memory the
+/// products take.
+fn integer_matmul<T>(
+    left: &Array<T>,
+    right: &Array<T>,
+    left_prop: MatProp,
+    right_prop: MatProp,
+) -> Array<T>
+where
+    T: HasAfEnum + ImplicitPromote<T, Output = T>,
+{
+    let transposed = |array: &Array<T>, prop: MatProp| match prop {
+        MatProp::TRANS => arrayfire::transpose(array, false),
+        _ => array.clone(),
+    };
+
+    // [m, k, batch] times [k, n, batch].
+    let left = transposed(left, left_prop);
+    let right = transposed(right, right_prop);
+    let (m, k, batch) = (left.dims()[0], left.dims()[1], left.dims()[2]);
+    let n = right.dims()[1];
+
+    let chunk = (INTEGER_MATMUL_CHUNK / (m * n * batch).max(1)).max(1);
+    let mut product = arrayfire::constant(0u8, Dim4::new(&[m, n, batch, 1])).cast::<T>();
+
+    for first in (0..k).step_by(chunk as usize) {
+        let last = (first + chunk).min(k) - 1;
+        let size = last - first + 1;
+
+        // Products are [m, chunk, n, batch], summed along the chunk.
+        let left_chunk = arrayfire::moddims(
+            &arrayfire::cols(&left, first as i64, last as i64),
+            Dim4::new(&[m, size, 1, batch]),
+        );
+        let right_chunk = arrayfire::moddims(
+            &arrayfire::rows(&right, first as i64, last as i64),
+            Dim4::new(&[1, size, n, batch]),
+        );
+        let products = arrayfire::mul(
+            &arrayfire::tile(&left_chunk, Dim4::new(&[1, 1, n, 1])),
+            &arrayfire::tile(&right_chunk, Dim4::new(&[m, 1, 1, 1])),
+            false,
+        );
+        let sums = arrayfire::sum(&products, 1).cast::<T>();
+
+        product = arrayfire::add(
+            &product,
+            &arrayfire::moddims(&sums, Dim4::new(&[m, n, batch, 1])),
+            false,
+        );
+    }
+
+    product
+}
+
+// Linear Algebra - LAPACK
+//
+// ArrayFire's LAPACK functions work on a single matrix, so batches
+// of matrices are computed one matrix at a time. Matrices are
+// computed in the dtype matmul uses, since LAPACK doesn't support
+// f16.
+
+// Maps the dtypes LAPACK supports to a list of arrays.
+macro_rules! map_lapack_array {
+    ($array:expr, $a:ident => $body:expr) => {
+        match $array {
+            ExAfArray::F32(ref $a) => Ok(to_exaf_arrays($body)),
+            ExAfArray::F64(ref $a) => Ok(to_exaf_arrays($body)),
+            ExAfArray::C64(ref $a) => Ok(to_exaf_arrays($body)),
+            ExAfArray::C128(ref $a) => Ok(to_exaf_arrays($body)),
+            ref array => Err(ExAfError::InvalidDType(format!(
+                "{}, matrices are computed in floating point",
+                array.dtype().name()
+            ))),
+        }
+    };
+}
+
+macro_rules! map_lapack_array_pair {
+    ($left:expr, $right:expr, ($a:ident, $b:ident) => $body:expr) => {
+        match ($left, $right) {
+            (ExAfArray::F32(ref $a), ExAfArray::F32(ref $b)) => Ok(to_exaf_arrays($body)),
+            (ExAfArray::F64(ref $a), ExAfArray::F64(ref $b)) => Ok(to_exaf_arrays($body)),
+            (ExAfArray::C64(ref $a), ExAfArray::C64(ref $b)) => Ok(to_exaf_arrays($body)),
+            (ExAfArray::C128(ref $a), ExAfArray::C128(ref $b)) => Ok(to_exaf_arrays($body)),
+            (ref left, ref right) => Err(mismatched_dtypes(left, right)),
+        }
+    };
+}
+
+#[rustler::nif]
+pub fn lu(array: ExAf) -> NifResult<(ExAf, ExAf, ExAf)> {
+    let exaf_array = array.resource.value()?;
+    let (m, n) = matrix_size(array.shape());
+    let k = m.min(n);
+
+    let outputs = try_catch_af("lu", &[&array], || {
+        map_matrices_as_lapack(
+            &[&exaf_array],
+            |arrays| map_lapack_array!(arrays[0], a => map_matrices(&[a], lu_factors)),
+        )
+    })?;
+
+    Ok((
+        ExAf::from_exaf_array(outputs[0].clone(), batch_shape(array.shape(), &[m, m])),
+        ExAf::from_exaf_array(outputs[1].clone(), batch_shape(array.shape(), &[m, k])),
+        ExAf::from_exaf_array(outputs[2].clone(), batch_shape(array.shape(), &[k, n])),
+    ))
+}
+
+// ArrayFire pivots the rows of the input, so that A(pivot, :) is
+// L U, while Nx returns a P such that A is P L U.
+fn lu_factors<T: HasAfEnum + FloatingPoint>(matrices: &[Array<T>]) -> Vec<Array<T>> {
+    let (lower, upper, pivot) = arrayfire::lu(&matrices[0]);
+
+    let size = matrices[0].dims()[0];
+    let identity = arrayfire::identity::<T>(Dim4::new(&[size, size, 1, 1]));
+    let pivoted = arrayfire::lookup(&identity, &pivot.cast::<u32>(), 0);
+
+    vec![arrayfire::transpose(&pivoted, false), lower, upper]
+}
+
+#[rustler::nif]
+pub fn qr(array: ExAf, complete: bool) -> NifResult<(ExAf, ExAf)> {
+    let exaf_array = array.resource.value()?;
+    let (m, n) = matrix_size(array.shape());
+    let k = if complete { m } else { m.min(n) };
+
+    let outputs = try_catch_af("qr", &[&array], || {
+        map_matrices_as_lapack(&[&exaf_array], |arrays| {
+            map_lapack_array!(arrays[0], a => map_matrices(&[a], |matrices| {
+                // ArrayFire always computes the complete decomposition.
+                let (q, r, _) = arrayfire::qr(&matrices[0]);
+
+                if complete {
+                    vec![q, r]
+                } else {
+                    vec![arrayfire::cols(&q, 0, k as i64 - 1), arrayfire::rows(&r, 0, k as i64 - 1)]
+                }
+            }))
+        })
+    })?;
+
+    Ok((
+        ExAf::from_exaf_array(outputs[0].clone(), batch_shape(array.shape(), &[m, k])),
+        ExAf::from_exaf_array(outputs[1].clone(), batch_shape(array.shape(), &[k, n])),
+    ))
+}
+
+#[rustler::nif]
+pub fn cholesky(array: ExAf) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let not_positive_definite = Cell::new(false);
+
+    let outputs = try_catch_af("cholesky", &[&array], || {
+        map_matrices_as_lapack(&[&exaf_array], |arrays| {
+            map_lapack_array!(arrays[0], a => map_matrices(&[a], |matrices| {
+                let (lower, info) = arrayfire::cholesky(&matrices[0], false);
+                if info != 0 {
+                    not_positive_definite.set(true);
+                }
+
+                vec![lower]
+            }))
+        })
+    })?;
+
+    if not_positive_definite.get() {
+        return Err(ExAfError::NotPositiveDefinite.into());
+    }
+
+    Ok(ExAf::from_exaf_array(
+        outputs[0].clone(),
+        array.shape().to_vec(),
+    ))
+}
+
+#[rustler::nif]
+pub fn triangular_solve(
+    a: ExAf,
+    b: ExAf,
+    left_side: bool,
+    lower: bool,
+    transpose_a: bool,
+) -> NifResult<ExAf> {
+    let exaf_a = a.resource.value()?;
+    let exaf_b = b.resource.value()?;
+    let b_matrices = as_matrices(&exaf_b, a.shape(), b.shape(), left_side);
+
+    let outputs = try_catch_af("triangular_solve", &[&a, &b], || {
+        map_matrices_as_lapack(&[&exaf_a, &b_matrices], |arrays| {
+            map_lapack_array_pair!(&arrays[0], &arrays[1], (left, right) => map_matrices(&[left, right], |matrices| {
+                // X op(A) = B is solved as op(A)^T X^T = B^T, and
+                // transposing A swaps its lower and upper triangles.
+                let transpose = transpose_a == left_side;
+                let a = if transpose {
+                    arrayfire::transpose(&matrices[0], false)
+                } else {
+                    matrices[0].clone()
+                };
+                let b = if left_side {
+                    matrices[1].clone()
+                } else {
+                    arrayfire::transpose(&matrices[1], false)
+                };
+
+                let triangle = if lower != transpose { MatProp::LOWER } else { MatProp::UPPER };
+                let x = arrayfire::solve(&a, &b, triangle);
+
+                if left_side {
+                    vec![x]
+                } else {
+                    vec![arrayfire::transpose(&x, false)]
+                }
+            }))
+        })
+    })?;
+
+    let x = apply_function_array!(outputs[0], moddims, dim_from_shape(b.shape()));
+
+    Ok(ExAf::from_exaf_array(x, b.shape().to_vec()))
+}
+
+#[rustler::nif]
+pub fn solve(a: ExAf, b: ExAf) -> NifResult<ExAf> {
+    let exaf_a = a.resource.value()?;
+    let exaf_b = b.resource.value()?;
+    let b_matrices = as_matrices(&exaf_b, a.shape(), b.shape(), true);
+
+    let singular = Cell::new(false);
+
+    let outputs = try_catch_af("solve", &[&a, &b], || {
+        map_matrices_as_lapack(&[&exaf_a, &b_matrices], |arrays| {
+            map_lapack_array_pair!(&arrays[0], &arrays[1], (left, right) => map_matrices(&[left, right], |matrices| {
+                let (_, upper, _) = arrayfire::lu(&matrices[0]);
+                if is_singular(&upper.to_exaf_array()) {
+                    singular.set(true);
+                }
+
+                vec![arrayfire::solve(&matrices[0], &matrices[1], MatProp::NONE)]
+            }))
+        })
+    })?;
+
+    if singular.get() {
+        return Err(ExAfError::SingularMatrix.into());
+    }
+
+    let x = apply_function_array!(outputs[0], moddims, dim_from_shape(b.shape()));
+
+    Ok(ExAf::from_exaf_array(x, b.shape().to_vec()))
+}
+
+#[rustler::nif]
+pub fn svd(array: ExAf, full_matrices: bool) -> NifResult<(ExAf, ExAf, ExAf)> {
+    let exaf_array = array.resource.value()?;
+    let (m, n) = matrix_size(array.shape());
+    let k = m.min(n);
+    let (u_cols, vt_rows) = if full_matrices { (m, n) } else { (k, k) };
+
+    let outputs = try_catch_af("svd", &[&array], || {
+        map_matrices_as_lapack(&[&exaf_array], |arrays| {
+            map_lapack_array!(arrays[0], a => map_matrices(&[a], |matrices| {
+                svd_factors(&matrices[0], u_cols, vt_rows)
+            }))
+        })
+    })?;
+
+    let u_shape = batch_shape(array.shape(), &[m, u_cols]);
+    let s_shape = batch_shape(array.shape(), &[k]);
+    let vt_shape = batch_shape(array.shape(), &[vt_rows, n]);
+
+    Ok((
+        ExAf::from_exaf_array(reshape_array(&outputs[0], &u_shape), u_shape),
+        ExAf::from_exaf_array(reshape_array(&outputs[1], &s_shape), s_shape),
+        ExAf::from_exaf_array(reshape_array(&outputs[2], &vt_shape), vt_shape),
+    ))
+}
+
+// ArrayFire always computes full matrices. Singular values are
+// real, they are returned in the dtype of the input so that every
+// output can be batched the same way.
+fn svd_factors<T>(matrix: &Array<T>, u_cols: u64, vt_rows: u64) -> Vec<Array<T>>
+where
+    T: HasAfEnum + FloatingPoint,
+    T::BaseType: HasAfEnum,
+{
+    let (u, s, vt) = arrayfire::svd(matrix);
+
+    vec![
+        arrayfire::cols(&u, 0, u_cols as i64 - 1),
+        s.cast::<T>(),
+        arrayfire::rows(&vt, 0, vt_rows as i64 - 1),
+    ]
+}
+
+#[rustler::nif]
+pub fn eigh(array: ExAf, max_iter: u64, eps: f64) -> NifResult<(ExAf, ExAf)> {
+    let exaf_array = array.resource.value()?;
+    let dtype = exaf_array.dtype();
+
+    if let ExAfDType::C64 | ExAfDType::C128 = dtype {
+        return Err(ExAfError::InvalidDType(format!(
+            "{}, eigh only supports real symmetric matrices",
+            dtype.name()
+        ))
+        .into());
+    }
+
+    let (n, _) = matrix_size(array.shape());
+    let eigenvals_shape = batch_shape(array.shape(), &[n]);
+
+    let outputs = try_catch_af("eigh", &[&array], || {
+        // ArrayFire has no eigensolver, so the whole batch is copied
+        // to the host once and solved there in double precision,
+        // which doesn't lose small eigenvalues to rounding.
+        let a = match_array!(exaf_array, a => a.cast::<f64>());
+        let mut matrices = vec![0.0; a.elements()];
+        a.host(&mut matrices);
+
+        let mut eigenvals = Vec::with_capacity(matrices.len() / n.max(1) as usize);
+        let mut eigenvecs = Vec::with_capacity(matrices.len());
+        for matrix in matrices.chunks((n * n).max(1) as usize) {
+            let (values, vectors) = symmetric_eigen(matrix, n as usize, max_iter, eps)?;
+            eigenvals.extend(values);
+            eigenvecs.extend(vectors);
+        }
+
+        let eigenvals = Array::new(&eigenvals, dim_from_shape(&eigenvals_shape));
+        let eigenvecs = Array::new(&eigenvecs, dim_from_shape(array.shape()));
+
+        Ok((
+            cast(&eigenvals.to_exaf_array(), dtype),
+            cast(&eigenvecs.to_exaf_array(), dtype),
+        ))
+    })?;
+
+    Ok((
+        ExAf::from_exaf_array(outputs.0, eigenvals_shape),
+        ExAf::from_exaf_array(outputs.1, array.shape().to_vec()),
+    ))
+}
+
+// Like Nx.BinaryBackend, eigh reduces the matrix to tridiagonal
+// form with Householder reflections and diagonalizes it with the
+// QR algorithm. Unlike Nx, every QR step is shifted by Wilkinson's
+// shift, so that eigenvalues of equal magnitude and opposite sign
+// converge too.
+//
+// Shifts change the order the eigenvalues converge in, so the
+// eigenvalues of every unreduced block of the tridiagonal matrix
+// are then sorted by decreasing magnitude, which is the order
+// unshifted QR steps converge to. Entries smaller than eps are
+// rounded to zero, also like Nx.
+fn symmetric_eigen(
+    matrix: &[f64],
+    n: usize,
+    max_iter: u64,
+    eps: f64,
+) -> Result<(Vec<f64>, Vec<f64>), ExAfError> {
+    let at = |i: usize, j: usize| i * n + j;
+    let mut t = matrix.to_vec();
+    let mut q = vec![0.0; n * n];
+    for i in 0..n {
+        q[at(i, i)] = 1.0;
+    }
+
+    tridiagonalize(&mut t, &mut q, n);
+
+    let negligible = |t: &[f64], i: usize| {
+        let off_diagonal = t[at(i + 1, i)].abs();
+        let diagonal = t[at(i, i)].abs() + t[at(i + 1, i + 1)].abs();
+
+        off_diagonal <= eps || off_diagonal <= f64::EPSILON * diagonal
+    };
+
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for i in 0..n {
+        if i + 1 == n || negligible(&t, i) {
+            blocks.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+
+    let mut iterations = 0;
+    let mut hi = n.saturating_sub(1);
+    while hi > 0 {
+        if negligible(&t, hi - 1) {
+            hi -= 1;
+            continue;
+        }
+
+        let mut lo = hi - 1;
+        while lo > 0 && !negligible(&t, lo - 1) {
+            lo -= 1;
+        }
+
+        if iterations == max_iter {
+            return Err(ExAfError::NotConverged(max_iter));
+        }
+
+        iterations += 1;
+        wilkinson_step(&mut t, &mut q, n, lo, hi);
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    for block in blocks {
+        order[block].sort_by(|&i, &j| {
+            let (left, right) = (t[at(i, i)].abs(), t[at(j, j)].abs());
+            right.partial_cmp(&left).unwrap_or(Ordering::Equal)
+        });
+    }
+
+    let round_to_zero = |x: f64| if x.abs() < eps { 0.0 } else { x };
+    let eigenvals = order.iter().map(|&i| round_to_zero(t[at(i, i)])).collect();
+    let eigenvecs = (0..n)
+        .flat_map(|row| order.iter().map(move |&col| (row, col)))
+        .map(|(row, col)| round_to_zero(q[at(row, col)]))
+        .collect();
+
+    Ok((eigenvals, eigenvecs))
+}
+
+/// Reduces the row major symmetric matrix t to tridiagonal form
+/// with Householder reflections, accumulating them into q.
+/// Columns that are already reduced are left alone, so that the
+/// blocks of block diagonal matrices stay apart.
+fn tridiagonalize(t: &mut [f64], q: &mut [f64], n: usize) {
+    for k in 0..n.saturating_sub(2) {
+        let mut v: Vec<f64> = (k + 1..n).map(|i| t[i * n + k]).collect();
+        if v[1..].iter().all(|&x| x == 0.0) {
+            continue;
+        }
+
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        v[0] += if v[0] < 0.0 { -norm } else { norm };
+        let scale = 2.0 / v.iter().map(|x| x * x).sum::<f64>();
+
+        // t = H t H and q = q H, where H = I - scale v v^T acts on
+        // the rows and columns after k.
+        for j in 0..n {
+            let dot: f64 = v
+                .iter()
+                .enumerate()
+                .map(|(i, x)| x * t[(k + 1 + i) * n + j])
+                .sum();
+            for (i, x) in v.iter().enumerate() {
+                t[(k + 1 + i) * n + j] -= scale * x * dot;
+            }
+        }
+
+        reflect_columns(t, n, k + 1, &v, scale);
+        reflect_columns(q, n, k + 1, &v, scale);
+    }
+}
+
+fn reflect_columns(matrix: &mut [f64], n: usize, offset: usize, v: &[f64], scale: f64) {
+    for i in 0..n {
+        let row = &mut matrix[i * n + offset..(i + 1) * n];
+        let dot: f64 = v.iter().zip(row.iter()).map(|(x, y)| x * y).sum();
+        for (y, x) in row.iter_mut().zip(v) {
+            *y -= scale * x * dot;
+        }
+    }
+}
+
+/// Applies one implicit QR step with Wilkinson's shift to the
+/// unreduced block lo..=hi of the tridiagonal matrix t, chasing
+/// the bulge down with Givens rotations, which are accumulated
+/// into q.
+fn wilkinson_step(t: &mut [f64], q: &mut [f64], n: usize, lo: usize, hi: usize) {
+    let at = |i: usize, j: usize| i * n + j;
+
+    let d = (t[at(hi - 1, hi - 1)] - t[at(hi, hi)]) / 2.0;
+    let e = t[at(hi, hi - 1)];
+    let sign = if d < 0.0 { -1.0 } else { 1.0 };
+    let shift = t[at(hi, hi)] - e * e / (d + sign * d.hypot(e));
+
+    let mut x = t[at(lo, lo)] - shift;
+    let mut z = t[at(lo + 1, lo)];
+
+    for k in lo..hi {
+        let r = x.hypot(z);
+        let (c, s) = if r == 0.0 { (1.0, 0.0) } else { (x / r, z / r) };
+
+        // t = G t G^T and q = q G^T, where G rotates k and k + 1.
+        for j in lo..=hi {
+            let (a, b) = (t[at(k, j)], t[at(k + 1, j)]);
+            t[at(k, j)] = c * a + s * b;
+            t[at(k + 1, j)] = c * b - s * a;
+        }
+
+        for i in lo..=hi {
+            let (a, b) = (t[at(i, k)], t[at(i, k + 1)]);
+            t[at(i, k)] = c * a + s * b;
+            t[at(i, k + 1)] = c * b - s * a;
+        }
+
+        for i in 0..n {
+            let (a, b) = (q[at(i, k)], q[at(i, k + 1)]);
+            q[at(i, k)] = c * a + s * b;
+            q[at(i, k + 1)] = c * b - s * a;
+        }
+
+        if k + 1 < hi {
+            x = t[at(k + 1, k)];
+            z = t[at(k + 2, k)];
+        }
+    }
+}
+
+fn reshape_array(array: &ExAfArray, shape: &[u64]) -> ExAfArray {
+    apply_function_array!(array, moddims, dim_from_shape(shape))
+}
+
+#[rustler::nif]
+pub fn invert(array: ExAf) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+
+    let singular = Cell::new(false);
+
+    let outputs = try_catch_af("invert", &[&array], || {
+        map_matrices_as_lapack(&[&exaf_array], |arrays| {
+            map_lapack_array!(arrays[0], a => map_matrices(&[a], |matrices| {
+                let (_, upper, _) = arrayfire::lu(&matrices[0]);
+                if is_singular(&upper.to_exaf_array()) {
+                    singular.set(true);
+                }
+
+                vec![arrayfire::inverse(&matrices[0], MatProp::NONE)]
+            }))
+        })
+    })?;
+
+    if singular.get() {
+        return Err(ExAfError::SingularMatrix.into());
+    }
+
+    Ok(ExAf::from_exaf_array(
+        outputs[0].clone(),
+        array.shape().to_vec(),
+    ))
+}
+
+/// Whether the LU factorization of a matrix has a pivot that is
+/// zero, up to the rounding error of factorizing it. ArrayFire
+/// doesn't check for singular matrices itself, and would return
+/// infinite or arbitrarily large values instead.
+fn is_singular(upper: &ExAfArray) -> bool {
+    let epsilon = match upper.dtype() {
+        ExAfDType::F32 | ExAfDType::C64 => f32::EPSILON as f64,
+        _ => f64::EPSILON,
+    };
+    let pivots = match_array!(upper, u => {
+        arrayfire::abs(&arrayfire::diag_extract(u, 0)).cast::<f64>()
+    });
+    let (max_pivot, _) = arrayfire::max_all(&pivots);
+    let (min_pivot, _) = arrayfire::min_all(&pivots);
+
+    min_pivot <= max_pivot * epsilon * pivots.elements() as f64
+}
+
+#[rustler::nif]
+pub fn determinant(array: ExAf) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let shape = batch_shape(array.shape(), &[]);
+    let dtype = exaf_array.dtype();
+
+    let result = catch_af("determinant", &[&array], || {
+        // Determinants are computed in double precision and rounded
+        // once to the output dtype, which keeps the determinants of
+        // integer matrices exact, like Nx computes them.
+        let determinants: Vec<(f64, f64)> = match dtype {
+            ExAfDType::C64 | ExAfDType::C128 => {
+                let a = match_array!(exaf_array, a => a.cast::<Complex64>());
+                split_matrices(&a).iter().map(arrayfire::det).collect()
+            }
+            _ => {
+                let a = match_array!(exaf_array, a => a.cast::<f64>());
+                split_matrices(&a).iter().map(arrayfire::det).collect()
+            }
+        };
+
+        // ArrayFire computes determinants on the host.
+        let dims = dim_from_shape(&shape);
+        match dtype {
+            ExAfDType::C64 => {
+                let values: Vec<Complex32> = determinants
+                    .iter()
+                    .map(|&(re, im)| Complex32::new(re as f32, im as f32))
+                    .collect();
+
+                Array::new(&values, dims).to_exaf_array()
+            }
+            ExAfDType::C128 => {
+                let values: Vec<Complex64> = determinants
+                    .iter()
+                    .map(|&(re, im)| Complex64::new(re, im))
+                    .collect();
+
+                Array::new(&values, dims).to_exaf_array()
+            }
+            _ => {
+                let values: Vec<f64> = determinants.iter().map(|&(re, _)| re).collect();
+
+                cast(&Array::new(&values, dims).to_exaf_array(), dtype)
+            }
+        }
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+/// Computes the outputs of the function in the dtype LAPACK
+/// supports, and casts them back to the dtype of the inputs.
+fn map_matrices_as_lapack<F>(
+    arrays: &[&ExAfArray],
+    function: F,
+) -> Result<Vec<ExAfArray>, ExAfError>
+where
+    F: FnOnce(&[ExAfArray]) -> Result<Vec<ExAfArray>, ExAfError>,
+{
+    let dtype = arrays[0].dtype();
+    let arrays: Vec<ExAfArray> = arrays.iter().map(|array| cast_to_lapack(array)).collect();
+
+    Ok(function(&arrays)?
+        .iter()
+        .map(|output| cast(output, dtype))
+        .collect())
+}
+
+fn cast_to_lapack(array: &ExAfArray) -> ExAfArray {
+    let dtype = matmul_dtype(array.dtype());
+
+    if array.dtype() == dtype {
+        array.clone()
+    } else {
+        cast(array, dtype)
+    }
+}
+
+/// Applies the function to every matrix of the arrays, and joins
+/// the outputs back into batches. Arrays hold matrices in Nx's row
+/// major layout, batched along dims 2 and 3.
+fn map_matrices<T, F>(arrays: &[&Array<T>], function: F) -> Vec<Array<T>>
+where
+    T: HasAfEnum,
+    F: Fn(&[Array<T>]) -> Vec<Array<T>>,
+{
+    let dims = arrays[0].dims();
+    let split: Vec<Vec<Array<T>>> = arrays.iter().map(|array| split_matrices(array)).collect();
+    let mut outputs: Vec<Vec<Array<T>>> = vec![];
+
+    for batch in 0..split[0].len() {
+        // Transposing gives ArrayFire the column major matrix.
+        let matrices: Vec<Array<T>> = split
+            .iter()
+            .map(|matrices| arrayfire::transpose(&matrices[batch], false))
+            .collect();
+
+        for (index, output) in function(&matrices).iter().enumerate() {
+            if outputs.len() <= index {
+                outputs.push(vec![]);
+            }
+
+            outputs[index].push(arrayfire::transpose(output, false));
+        }
+    }
+
+    outputs
+        .iter()
+        .map(|matrices| {
+            let batches: Vec<Array<T>> = matrices
+                .chunks(dims[2] as usize)
+                .map(|chunk| join_chunks(chunk.iter().collect(), 2))
+                .collect();
+
+            join_chunks(batches.iter().collect(), 3)
+        })
+        .collect()
+}
+
+fn split_matrices<T: HasAfEnum>(array: &Array<T>) -> Vec<Array<T>> {
+    let dims = array.dims();
+    let mut matrices = Vec::with_capacity((dims[2] * dims[3]) as usize);
+
+    for outer in 0..dims[3] as i32 {
+        for inner in 0..dims[2] as i32 {
+            let seqs = [
+                Seq::default(),
+                Seq::default(),
+                Seq::new(inner, inner, 1),
+                Seq::new(outer, outer, 1),
+            ];
+
+            matrices.push(arrayfire::index(array, &seqs));
+        }
+    }
+
+    matrices
+}
+
+/// Views right hand sides as matrices, vectors are columns when
+/// solving on the left side and rows on the right side.
+fn as_matrices(array: &ExAfArray, a_shape: &[u64], shape: &[u64], left_side: bool) -> ExAfArray {
+    if shape.len() == a_shape.len() {
+        return array.clone();
+    }
+
+    let mut matrix_shape = shape.to_vec();
+    let position = if left_side {
+        shape.len()
+    } else {
+        shape.len() - 1
+    };
+    matrix_shape.insert(position, 1);
+
+    apply_function_array!(array, moddims, dim_from_shape(&matrix_shape))
+}
+
+fn matrix_size(shape: &[u64]) -> (u64, u64) {
+    (shape[shape.len() - 2], shape[shape.len() - 1])
+}
+
+fn batch_shape(shape: &[u64], matrix_shape: &[u64]) -> Vec<u64> {
+    let mut batch_shape = shape[..shape.len() - 2].to_vec();
+    batch_shape.extend(matrix_shape);
+
+    batch_shape
+}
+
+fn to_exaf_arrays<T>(arrays: Vec<Array<T>>) -> Vec<ExAfArray>
+where
+    Array<T>: ArrayToExAfArray,
+{
+    arrays.iter().map(|array| array.to_exaf_array()).collect()
+}
+
+// Indexing
+
+#[rustler::nif]
+pub fn slice(
+    array: ExAf,
+    start_indices: Vec<u64>,
+    lengths: Vec<u64>,
+    strides: Vec<u64>,
+) -> NifResult<ExAf> {
+    let shape: Vec<u64> = lengths
+        .iter()
+        .zip(strides.iter())
+        .map(|(length, stride)| (length + stride - 1) / stride)
+        .collect();
+    let exaf_array = array.resource.value()?;
+
+    let result = catch_af("slice", &[&array], || {
+        slice_array(&exaf_array, array.shape(), &start_indices, &shape, &strides)
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+#[rustler::nif]
+pub fn put_slice(array: ExAf, start_indices: Vec<u64>, slice: ExAf) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let exaf_slice = slice.resource.value()?;
+    let strides = vec![1; start_indices.len()];
+
+    let result = try_catch_af("put_slice", &[&array, &slice], || {
+        assign_slice(
+            &exaf_array,
+            array.shape(),
+            &start_indices,
+            &exaf_slice,
+            slice.shape(),
+            &strides,
+        )
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+}
+
+#[rustler::nif]
+pub fn pad(array: ExAf, pad_value: ExAf, config: Vec<(i64, i64, u64)>) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let exaf_value = pad_value.resource.value()?;
+
+    let (result, shape) = try_catch_af("pad", &[&array, &pad_value], || {
+        pad_array(&exaf_array, array.shape(), &exaf_value, &config)
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+// Out of range indices are clamped to the axis, the way Nx's
+// compiled backends handle them.
+
+#[rustler::nif]
+pub fn take(array: ExAf, indices: ExAf, axis: usize) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let exaf_indices = indices.resource.value()?;
+
+    let mut shape = array.shape()[..axis].to_vec();
+    shape.extend(indices.shape());
+    shape.extend(&array.shape()[axis + 1..]);
+
+    let result = catch_af("take", &[&array, &indices], || {
+        let view = axis_view(array.shape(), axis);
+        let viewed = apply_function_array!(exaf_array, moddims, view);
+        let indices = arrayfire::flat(&clamp_indices(&exaf_indices, view[1]));
+        let span = Seq::<i32>::default();
+
+        let mut indexer = Indexer::default();
+        indexer.set_index(&span, 0, None);
+        indexer.set_index(&indices, 1, None);
+        indexer.set_index(&span, 2, None);
+
+        let taken = map_array!(viewed, a => arrayfire::index_gen(a, indexer));
+
+        apply_function_array!(taken, moddims, dim_from_shape(&shape))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+#[rustler::nif]
+pub fn take_along_axis(array: ExAf, indices: ExAf, axis: usize) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let exaf_indices = indices.resource.value()?;
+    let shape = indices.shape().to_vec();
+
+    let result = catch_af("take_along_axis", &[&array, &indices], || {
+        let view = axis_view(array.shape(), axis);
+        let (inner, size, outer) = (view[0] as u32, view[1] as u32, view[2]);
+
+        // The linear index of [i, index, o] in the array viewed
+        // as [inner, size, outer].
+        let dims = axis_view(&shape, axis);
+        let indices = arrayfire::moddims(&clamp_indices(&exaf_indices, view[1]), dims);
+        let inner_offsets = arrayfire::iota::<u32>(
+            Dim4::new(&[dims[0], 1, 1, 1]),
+            Dim4::new(&[1, dims[1], dims[2], 1]),
+        );
+        let outer_offsets = arrayfire::iota::<u32>(
+            Dim4::new(&[1, 1, outer, 1]),
+            Dim4::new(&[dims[0], dims[1], 1, 1]),
+        );
+
+        let linear = arrayfire::add(
+            &arrayfire::add(
+                &inner_offsets,
+                &arrayfire::mul(&indices, &inner, false),
+                false,
+            ),
+            &arrayfire::mul(&outer_offsets, &(inner * size), false),
+            false,
+        );
+
+        let taken = map_array!(exaf_array, a => {
+            arrayfire::lookup(&arrayfire::flat(a), &arrayfire::flat(&linear), 0)
+        });
+
+        apply_function_array!(taken, moddims, dim_from_shape(&shape))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+#[rustler::nif]
+pub fn gather(array: ExAf, indices: ExAf) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let exaf_indices = indices.resource.value()?;
+
+    let shape = indices.shape()[..indices.shape().len() - 1].to_vec();
+
+    let result = catch_af("gather", &[&array, &indices], || {
+        let linear = linear_indices(&exaf_indices, indices.shape(), array.shape());
+
+        let gathered = map_array!(exaf_array, a => {
+            arrayfire::lookup(&arrayfire::flat(a), &linear, 0)
+        });
+
+        apply_function_array!(gathered, moddims, dim_from_shape(&shape))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+macro_rules! scatter_op {
+    ($op_name:ident, $scatter:ident) => {
+        #[rustler::nif]
+        pub fn $op_name(target: ExAf, indices: ExAf, updates: ExAf) -> NifResult<ExAf> {
+            let exaf_target = target.resource.value()?;
+            let exaf_indices = indices.resource.value()?;
+            let exaf_updates = updates.resource.value()?;
+
+            let result = try_catch_af(
+                stringify!($op_name),
+                &[&target, &indices, &updates],
+                || {
+                    let linear = linear_indices(&exaf_indices, indices.shape(), target.shape());
+
+                    map_array_pair!(exaf_target, exaf_updates, (t, u) => $scatter(t, &linear, u))
+                },
+            )?;
+
+            Ok(ExAf::from_exaf_array(result, target.shape().to_vec()))
+        }
+    };
+}
+
+scatter_op!(indexed_add, scatter_add);
+scatter_op!(indexed_put, scatter_put);
+
+fn scatter_add<T>(target: &Array<T>, indices: &Array<u32>, updates: &Array<T>) -> Array<T>
+where
+    T: HasAfEnum + ImplicitPromote<T, Output = T>,
+{
+    // Sum the updates for repeated indices first, so that every
+    // index is only assigned once.
+    let (keys, values) = arrayfire::sort_by_key(indices, &arrayfire::flat(updates), 0, true);
+    let (keys, sums) = arrayfire::sum_by_key(&keys, &values, 0);
+
+    let mut flat = arrayfire::flat(target);
+    let sums = arrayfire::add(
+        &arrayfire::lookup(&flat, &keys, 0),
+        &sums.cast::<T>(),
+        false,
+    );
+
+    let mut indexer = Indexer::default();
+    indexer.set_index(&keys, 0, None);
+    arrayfire::assign_gen(&mut flat, &indexer, &sums);
+
+    arrayfire::moddims(&flat, target.dims())
+}
+
+fn scatter_put<T: HasAfEnum>(
+    target: &Array<T>,
+    indices: &Array<u32>,
+    updates: &Array<T>,
+) -> Array<T> {
+    let mut flat = arrayfire::flat(target);
+
+    let mut indexer = Indexer::default();
+    indexer.set_index(indices, 0, None);
+    arrayfire::assign_gen(&mut flat, &indexer, &arrayfire::flat(updates));
+
+    arrayfire::moddims(&flat, target.dims())
+}
+
+/// Turns indices whose last axis holds full indices into an array
+/// of the given shape into flat linear indices.
+fn linear_indices(indices: &ExAfArray, indices_shape: &[u64], shape: &[u64]) -> Array<u32> {
+    let rank = shape.len() as u64;
+    let count = indices_shape.iter().product::<u64>() / rank;
+    let dims = Dim4::new(&[rank, count, 1, 1]);
+
+    let indices: Array<i64> = match_array!(indices, a => a.cast::<i64>());
+    let indices = arrayfire::moddims(&indices, dims);
+
+    let mut strides = vec![1; shape.len()];
+    for axis in (0..shape.len() - 1).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1] as i64;
+    }
+    let maxes: Vec<i64> = shape.iter().map(|&dim| dim as i64 - 1).collect();
+
+    let tiles = Dim4::new(&[1, count, 1, 1]);
+    let maxes = arrayfire::tile(&Array::new(&maxes, Dim4::new(&[rank, 1, 1, 1])), tiles);
+    let strides = arrayfire::tile(&Array::new(&strides, Dim4::new(&[rank, 1, 1, 1])), tiles);
+
+    let zero = arrayfire::constant(0i64, dims);
+    let indices = arrayfire::clamp(&indices, &zero, &maxes, false);
+    let linear = arrayfire::sum(&arrayfire::mul(&indices, &strides, false), 0);
+
+    arrayfire::flat(&linear).cast::<u32>()
+}
+
+fn clamp_indices(indices: &ExAfArray, size: u64) -> Array<u32> {
+    let indices: Array<i64> = match_array!(indices, a => a.cast::<i64>());
+    let indices = arrayfire::clamp(&indices, &0i64, &(size as i64 - 1), false);
+
+    indices.cast::<u32>()
+}
+
+/// Pads an array with a scalar value, returning the padded
+/// array and its shape.
+fn pad_array(
+    array: &ExAfArray,
+    shape: &[u64],
+    value: &ExAfArray,
+    config: &[(i64, i64, u64)],
+) -> Result<(ExAfArray, Vec<u64>), ExAfError> {
+    // Negative padding crops the tensor, so everything is padded
+    // by the positive part of the config first and then cropped.
+    let positive = |pad: i64| pad.max(0) as u64;
+    let negative = |pad: i64| (-pad).max(0) as u64;
+
+    let padded_shape: Vec<u64> = shape
+        .iter()
+        .zip(config.iter())
+        .map(|(&dim, &(low, high, interior))| {
+            dim + positive(low) + positive(high) + dim.saturating_sub(1) * interior
+        })
+        .collect();
+    let out_shape: Vec<u64> = padded_shape
+        .iter()
+        .zip(config.iter())
+        .map(|(&dim, &(low, high, _))| dim - negative(low) - negative(high))
+        .collect();
+
+    let start_indices: Vec<u64> = config.iter().map(|&(low, _, _)| positive(low)).collect();
+    let strides: Vec<u64> = config
+        .iter()
+        .map(|&(_, _, interior)| interior + 1)
+        .collect();
+    let crop_indices: Vec<u64> = config.iter().map(|&(low, _, _)| negative(low)).collect();
+
+    let padded = if shape.len() <= 4 && config.iter().all(|&(_, _, interior)| interior == 0) {
+        let edges = |pads: Vec<u64>| {
+            let mut dims = [0; 4];
+            for (dim, pad) in dims.iter_mut().zip(pads.into_iter().rev()) {
+                *dim = pad;
+            }
+
+            Dim4::new(&dims)
+        };
+        let begin = edges(config.iter().map(|&(low, _, _)| positive(low)).collect());
+        let end = edges(config.iter().map(|&(_, high, _)| positive(high)).collect());
+
+        pad_edges(array, value, begin, end)?
+    } else {
+        // Interior padding, or axes folded into the same dim, are
+        // written as a strided slice into an array of the value.
+        let dims = dim_from_shape(&padded_shape);
+        let filled = map_array!(value, a => arrayfire::tile(a, dims));
+
+        assign_slice(
+            &filled,
+            &padded_shape,
+            &start_indices,
+            array,
+            shape,
+            &strides,
+        )?
+    };
+
+    let cropped = slice_array(
+        &padded,
+        &padded_shape,
+        &crop_indices,
+        &out_shape,
+        &vec![1; out_shape.len()],
+    );
+
+    Ok((cropped, out_shape))
+}
+
+/// Pads the edges of an array with a scalar value. ArrayFire's pad
+/// only fills with zeros, so the value is selected everywhere the
+/// padded array of ones is zero.
+fn pad_edges(
+    array: &ExAfArray,
+    value: &ExAfArray,
+    begin: Dim4,
+    end: Dim4,
+) -> Result<ExAfArray, ExAfError> {
+    map_array_pair!(array, value, (a, v) => {
+        let padded = arrayfire::pad(a, begin, end, BorderType::ZERO);
+        let ones = arrayfire::constant(1u8, a.dims());
+        let inside = arrayfire::pad(&ones, begin, end, BorderType::ZERO).cast::<bool>();
+
+        arrayfire::select(&padded, &inside, &arrayfire::tile(v, padded.dims()))
+    })
+}
+
+/// Takes a strided slice of the given shape out of an array.
+fn slice_array(
+    array: &ExAfArray,
+    shape: &[u64],
+    start_indices: &[u64],
+    slice_shape: &[u64],
+    strides: &[u64],
+) -> ExAfArray {
+    if slice_shape.is_empty() {
+        array.clone()
+    } else if slice_shape.len() <= 4 {
+        let seqs = slice_seqs(start_indices, slice_shape, strides);
+
+        map_array!(array, a => arrayfire::index(a, &seqs))
+    } else {
+        let indices = slice_indices(shape, start_indices, slice_shape, strides);
+        let sliced = map_array!(array, a => arrayfire::lookup(&arrayfire::flat(a), &indices, 0));
+
+        apply_function_array!(sliced, moddims, dim_from_shape(slice_shape))
+    }
+}
+
+/// Writes a slice into a strided region of an array, both arrays
+/// must have the same dtype.
+fn assign_slice(
+    array: &ExAfArray,
+    shape: &[u64],
+    start_indices: &[u64],
+    slice: &ExAfArray,
+    slice_shape: &[u64],
+    strides: &[u64],
+) -> Result<ExAfArray, ExAfError> {
+    if slice_shape.is_empty() {
+        Ok(slice.clone())
+    } else if slice_shape.len() <= 4 {
+        let seqs = slice_seqs(start_indices, slice_shape, strides);
+
+        map_array_pair!(array, slice, (a, s) => {
+            let mut a = a.clone();
+            arrayfire::assign_seq(&mut a, &seqs, s);
+            a
+        })
+    } else {
+        let indices = slice_indices(shape, start_indices, slice_shape, strides);
+        let mut indexer = Indexer::default();
+        indexer.set_index(&indices, 0, None);
+
+        map_array_pair!(array, slice, (a, s) => {
+            let mut flat = arrayfire::flat(a);
+            arrayfire::assign_gen(&mut flat, &indexer, &arrayfire::flat(s));
+            arrayfire::moddims(&flat, a.dims())
+        })
+    }
+}
+
+/// ArrayFire sequences selecting a slice of the given shape,
+/// with inclusive ends.
+fn slice_seqs(start_indices: &[u64], shape: &[u64], strides: &[u64]) -> Vec<Seq<i32>> {
+    start_indices
+        .iter()
+        .zip(shape.iter())
+        .zip(strides.iter())
+        .rev()
+        .map(|((&start, &length), &stride)| {
+            let end = start + (length - 1) * stride;
+            Seq::new(start as i32, end as i32, stride as i32)
+        })
+        .collect()
+}
+
+/// The linear index of every element of a slice of the given
+/// shape, in row-major order. Used when the axes of the array
+/// are folded and can't be indexed on their own.
+fn slice_indices(
+    shape: &[u64],
+    start_indices: &[u64],
+    slice_shape: &[u64],
+    strides: &[u64],
+) -> Array<u64> {
+    let dim_strides = row_major_strides(shape);
+
+    let offset = start_indices
+        .iter()
+        .zip(dim_strides.iter())
+        .map(|(start, dim_stride)| start * dim_stride)
+        .sum();
+    let steps: Vec<u64> = strides
+        .iter()
+        .zip(dim_strides.iter())
+        .map(|(stride, dim_stride)| stride * dim_stride)
+        .collect();
+
+    strided_indices(offset, slice_shape, &steps)
+}
+
+/// The linear index of every element of a view of the given shape,
+/// in row-major order, where moving along an axis of the view moves
+/// by its step in the array.
+///
+/// Indices are computed on the device and in u64, so that they
+/// neither go through the host nor wrap on large arrays.
+fn strided_indices(offset: u64, shape: &[u64], steps: &[u64]) -> Array<u64> {
+    let size: u64 = shape.iter().product();
+    let flat = Dim4::new(&[size, 1, 1, 1]);
+    let mut indices = arrayfire::constant(offset, flat);
+
+    for (axis, &step) in steps.iter().enumerate() {
+        if shape[axis] > 1 {
+            let view = axis_view(shape, axis);
+            let positions = arrayfire::iota::<u64>(
+                Dim4::new(&[1, view[1], 1, 1]),
+                Dim4::new(&[view[0], 1, view[2], 1]),
+            );
+            let offsets = arrayfire::mul(&arrayfire::moddims(&positions, flat), &step, false);
+
+            indices = arrayfire::add(&indices, &offsets, false);
+        }
+    }
+
+    indices
+}
+
+fn row_major_strides(shape: &[u64]) -> Vec<u64> {
+    let rank = shape.len();
+    let mut strides = vec![1; rank];
+    for axis in (0..rank.saturating_sub(1)).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1];
+    }
+
+    strides
+}
+
+// Convolution
+
+#[derive(NifMap)]
+pub struct ConvOptions {
+    strides: Vec<u64>,
+    padding: Vec<(i64, i64)>,
+    input_dilation: Vec<u64>,
+    kernel_dilation: Vec<u64>,
+    feature_group_size: u64,
+    batch_group_size: u64,
+    input_permutation: Vec<usize>,
+    kernel_permutation: Vec<usize>,
+    output_permutation: Vec<usize>,
+}
+
+#[rustler::nif]
+pub fn conv(array: ExAf, kernel: ExAf, opts: ConvOptions) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+    let exaf_kernel = kernel.resource.value()?;
+
+    let (result, shape) = try_catch_af("conv", &[&array, &kernel], || {
+        // The kernel is cast along with the input, so that both are
+        // computed in the same dtype whatever the kernel's dtype is.
+        let dtype = exaf_array.dtype();
+        let compute_dtype = matmul_dtype(dtype);
+
+        let (result, shape) = convolve(
+            &cast(&exaf_array, compute_dtype),
+            array.shape(),
+            &cast(&exaf_kernel, compute_dtype),
+            kernel.shape(),
+            &opts,
+        )?;
+
+        Ok((cast(&result, dtype), shape))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+fn convolve(
+    array: &ExAfArray,
+    shape: &[u64],
+    kernel: &ExAfArray,
+    kernel_shape: &[u64],
+    opts: &ConvOptions,
+) -> Result<(ExAfArray, Vec<u64>), ExAfError> {
+    // Bring the input to [batch, channels, spatial...] and the
+    // kernel to [out channels, in channels, spatial...].
+    let input_shape: Vec<u64> = opts
+        .input_permutation
+        .iter()
+        .map(|&axis| shape[axis])
+        .collect();
+    let input = permute(array, shape, &opts.input_permutation);
+
+    let permuted_kernel_shape: Vec<u64> = opts
+        .kernel_permutation
+        .iter()
+        .map(|&axis| kernel_shape[axis])
+        .collect();
+    let kernel = permute(kernel, kernel_shape, &opts.kernel_permutation);
+    let kernel_shape = permuted_kernel_shape;
+
+    // Batch groups are moved from the batch axis to the channels
+    // axis, where they are computed like feature groups.
+    let (input, input_shape, groups) = if opts.batch_group_size > 1 {
+        let batch_groups = opts.batch_group_size;
+
+        let mut split_shape = vec![batch_groups, input_shape[0] / batch_groups];
+        split_shape.extend(&input_shape[1..]);
+        let mut axes = vec![1, 0];
+        axes.extend(2..split_shape.len());
+
+        let mut grouped_shape = vec![input_shape[0] / batch_groups, batch_groups * input_shape[1]];
+        grouped_shape.extend(&input_shape[2..]);
+
+        (
+            permute(&input, &split_shape, &axes),
+            grouped_shape,
+            batch_groups,
+        )
+    } else {
+        (input, input_shape, opts.feature_group_size)
+    };
+
+    let spatial = input_shape.len() - 2;
+    let window = &kernel_shape[2..];
+
+    let out_spatial: Vec<u64> = (0..spatial)
+        .map(|i| {
+            let (low, high) = opts.padding[i];
+            let dilated_input = (input_shape[i + 2] - 1) * opts.input_dilation[i] + 1;
+            let padded = (dilated_input as i64 + low + high) as u64;
+            let dilated_window = (window[i] - 1) * opts.kernel_dilation[i] + 1;
+
+            (padded - dilated_window) / opts.strides[i] + 1
+        })
+        .collect();
+
+    let batch = input_shape[0];
+    let out_channels = kernel_shape[0];
+
+    let result = if spatial == 2
+        && groups == 1
+        && matches!(input.dtype(), ExAfDType::F32 | ExAfDType::F64)
+        && opts.input_dilation.iter().all(|&dilation| dilation == 1)
+        && opts
+            .padding
+            .iter()
+            .all(|&(low, high)| low == high && low >= 0)
+    {
+        // [batch, channels, h, w] is [w, h, channels, batch] in
+        // ArrayFire, which is the layout convolve2_nn expects.
+        let dims = |values: &[u64]| Dim4::new(&[values[1], values[0], 1, 1]);
+        let padding: Vec<u64> = opts.padding.iter().map(|&(low, _)| low as u64).collect();
+
+        convolve2(
+            &input,
+            &kernel,
+            dims(&opts.strides),
+            dims(&padding),
+            dims(&opts.kernel_dilation),
+        )?
+    } else {
+        // Input dilations are interior padding of the spatial axes.
+        let mut config = vec![(0, 0, 0); 2];
+        config.extend((0..spatial).map(|i| {
+            let (low, high) = opts.padding[i];
+            (low, high, opts.input_dilation[i] - 1)
+        }));
+        let zero = WindowOp::Sum.identity(input.dtype());
+        let (padded, padded_shape) = pad_array(&input, &input_shape, &zero, &config)?;
+
+        im2col_convolve(
+            &padded,
+            &padded_shape,
+            &kernel,
+            &kernel_shape,
+            &out_spatial,
+            groups,
+            opts,
+        )?
+    };
+
+    let mut result_shape = vec![batch, out_channels];
+    result_shape.extend(&out_spatial);
+
+    // The output permutation gives the position of every axis of
+    // [batch, channels, spatial...] in the output.
+    let mut axes = vec![0; result_shape.len()];
+    for (axis, &position) in opts.output_permutation.iter().enumerate() {
+        axes[position] = axis;
+    }
+    let out_shape: Vec<u64> = axes.iter().map(|&axis| result_shape[axis]).collect();
+
+    Ok((permute(&result, &result_shape, &axes), out_shape))
+}
+
+fn convolve2(
+    input: &ExAfArray,
+    kernel: &ExAfArray,
+    strides: Dim4,
+    padding: Dim4,
+    dilations: Dim4,
+) -> Result<ExAfArray, ExAfError> {
+    match (input, kernel) {
+        (ExAfArray::F32(i), ExAfArray::F32(k)) => {
+            Ok(convolve2_flipped(i, k, strides, padding, dilations).to_exaf_array())
+        }
+        (ExAfArray::F64(i), ExAfArray::F64(k)) => {
+            Ok(convolve2_flipped(i, k, strides, padding, dilations).to_exaf_array())
+        }
+        (input, kernel) => Err(mismatched_dtypes(input, kernel)),
+    }
+}
+
+// ArrayFire convolves, while Nx correlates, so the kernel is
+// flipped along both spatial dims.
+fn convolve2_flipped<T: HasAfEnum + FloatingPoint>(
+    input: &Array<T>,
+    kernel: &Array<T>,
+    strides: Dim4,
+    padding: Dim4,
+    dilations: Dim4,
+) -> Array<T> {
+    let flipped = arrayfire::flip(&arrayfire::flip(kernel, 0), 1);
+
+    arrayfire::convolve2_nn(input, &flipped, strides, padding, dilations)
+}
+
+/// Convolves a padded input with one matmul per group, over the
+/// columns of input elements every output element is made of.
+fn im2col_convolve(
+    padded: &ExAfArray,
+    padded_shape: &[u64],
+    kernel: &ExAfArray,
+    kernel_shape: &[u64],
+    out_spatial: &[u64],
+    groups: u64,
+    opts: &ConvOptions,
+) -> Result<ExAfArray, ExAfError> {
+    let batch = padded_shape[0];
+    let channels = padded_shape[1] / groups;
+    let out_channels = kernel_shape[0] / groups;
+    let out_size: u64 = out_spatial.iter().product();
+
+    let positions = window_positions(&kernel_shape[2..]);
+    let window_size = positions.len() as u64;
+
+    let mut slice_shape = vec![batch, channels];
+    slice_shape.extend(out_spatial);
+    let mut strides = vec![1, 1];
+    strides.extend(&opts.strides);
+
+    let mut kernel_slice_shape = kernel_shape.to_vec();
+    kernel_slice_shape[0] = out_channels;
+
+    let outputs = (0..groups)
+        .map(|group| {
+            // Columns are [batch, channels, positions, out_size].
+            let columns = if padded_shape.len() <= 4 {
+                let views: Vec<ExAfArray> = positions
+                    .iter()
+                    .map(|position| {
+                        let mut start_indices = vec![0, group * channels];
+                        start_indices.extend(window_offsets(position, &opts.kernel_dilation));
+
+                        let slice = slice_array(
+                            padded,
+                            padded_shape,
+                            &start_indices,
+                            &slice_shape,
+                            &strides,
+                        );
+                        let view = Dim4::new(&[out_size, 1, batch * channels, 1]);
+
+                        apply_function_array!(slice, moddims, view)
+                    })
+                    .collect();
+
+                join_arrays!(views, 1, U8, U16, U32, U64, S16, S32, S64, F16, F32, F64, C64, C128)?
+            } else {
+                gather_columns(
+                    padded,
+                    padded_shape,
+                    group * channels,
+                    &slice_shape,
+                    &strides,
+                    &positions,
+                    &opts.kernel_dilation,
+                )
+            };
+
+            // One row of [channels, positions] per output element.
+            let columns = permute(
+                &columns,
+                &[batch, channels, window_size, out_size],
+                &[0, 3, 1, 2],
+            );
+            let columns = apply_function_array!(
+                columns,
+                moddims,
+                Dim4::new(&[channels * window_size, batch * out_size, 1, 1])
+            );
+
+            let mut kernel_start_indices = vec![0; kernel_shape.len()];
+            kernel_start_indices[0] = group * out_channels;
+            let kernel_group = slice_array(
+                kernel,
+                kernel_shape,
+                &kernel_start_indices,
+                &kernel_slice_shape,
+                &vec![1; kernel_shape.len()],
+            );
+            let kernel_group = apply_function_array!(
+                kernel_group,
+                moddims,
+                Dim4::new(&[channels * window_size, out_channels, 1, 1])
+            );
+
+            matmul(&kernel_group, &columns, MatProp::TRANS, MatProp::NONE)
+        })
+        .collect::<Result<Vec<ExAfArray>, ExAfError>>()?;
+
+    // The output is [batch, out_size, out channels], with the
+    // channels of every group next to each other.
+    let joined =
+        join_arrays!(outputs, 0, U8, U16, U32, U64, S16, S32, S64, F16, F32, F64, C64, C128)?;
+
+    let spatial = out_spatial.len();
+    let mut joined_shape = vec![batch];
+    joined_shape.extend(out_spatial);
+    joined_shape.push(out_channels * groups);
+
+    let mut axes = vec![0, spatial + 1];
+    axes.extend(1..=spatial);
+
+    Ok(permute(&joined, &joined_shape, &axes))
+}
+
+/// Gathers the columns of every window position at once, as
+/// [batch, channels, positions, out_size]. Folded axes can't be
+/// sliced with sequences, so the indices of the slice at the first
+/// position are computed once and shifted to every other position.
+fn gather_columns(
+    padded: &ExAfArray,
+    padded_shape: &[u64],
+    first_channel: u64,
+    slice_shape: &[u64],
+    strides: &[u64],
+    positions: &[Vec<u64>],
+    dilations: &[u64],
+) -> ExAfArray {
+    let (batch, channels) = (slice_shape[0], slice_shape[1]);
+    let out_size: u64 = slice_shape[2..].iter().product();
+    let window_size = positions.len() as u64;
+
+    let mut start_indices = vec![0; padded_shape.len()];
+    start_indices[1] = first_channel;
+    let first = slice_indices(padded_shape, &start_indices, slice_shape, strides);
+    let first = arrayfire::moddims(&first, Dim4::new(&[out_size, 1, batch * channels, 1]));
+
+    let spatial_strides = &row_major_strides(padded_shape)[2..];
+    let shifts: Vec<u64> = positions
+        .iter()
+        .map(|position| {
+            window_offsets(position, dilations)
+                .zip(spatial_strides.iter())
+                .map(|(offset, stride)| offset * stride)
+                .sum()
+        })
+        .collect();
+    let shifts = Array::new(&shifts, Dim4::new(&[1, window_size, 1, 1]));
+
+    let indices = arrayfire::add(
+        &arrayfire::tile(&first, Dim4::new(&[1, window_size, 1, 1])),
+        &arrayfire::tile(&shifts, Dim4::new(&[out_size, 1, batch * channels, 1])),
+        false,
+    );
+
+    map_array!(padded, a => {
+        let gathered = arrayfire::lookup(&arrayfire::flat(a), &arrayfire::flat(&indices), 0);
+        arrayfire::moddims(&gathered, indices.dims())
+    })
+}
+
+/// The offset of a window position along each spatial axis.
+fn window_offsets<'a>(position: &'a [u64], dilations: &'a [u64]) -> impl Iterator<Item = u64> + 'a {
+    position
+        .iter()
+        .zip(dilations.iter())
+        .map(|(index, dilation)| index * dilation)
+}
+
+// FFT
+
+// Transforms run along the last axis, which is ArrayFire's dim 0,
+// padding it with zeros or truncating it to the given length.
+//
+// Nx's fft and ifft are one dimensional and always return the
+// full complex spectrum, so inputs are cast to complex and the
+// complex transforms are used: fft_r2c only returns half of the
+// spectrum, fft_c2r assumes a conjugate symmetric input, and
+// fft2/fft3 transform more than one axis.
+macro_rules! fft_op {
+    ($op_name:ident, $af_op:ident, $inverse:expr) => {
+        #[rustler::nif]
+        pub fn $op_name(array: ExAf, length: u64) -> NifResult<ExAf> {
+            let exaf_array = array.resource.value()?;
+
+            let mut shape = array.shape().to_vec();
+            let last = shape.len() - 1;
+            shape[last] = length;
+
+            // Inverse transforms are scaled by 1 / length, like in Nx.
+            let norm_factor = if $inverse { 1.0 / length as f64 } else { 1.0 };
+
+            let result = try_catch_af(stringify!($op_name), &[&array], || match exaf_array {
+                ExAfArray::C64(ref a) => {
+                    Ok(arrayfire::$af_op(a, norm_factor, length as i64).to_exaf_array())
+                }
+                ExAfArray::C128(ref a) => {
+                    Ok(arrayfire::$af_op(a, norm_factor, length as i64).to_exaf_array())
+                }
+                ref array => Err(ExAfError::InvalidDType(format!(
+                    "{}, transforms are computed in c64 or c128",
+                    array.dtype().name()
+                ))),
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, shape))
+        }
+    };
+}
+
+fft_op!(fft, fft, false);
+fft_op!(ifft, ifft, true);
+
+// Shape
+
+#[rustler::nif]
+pub fn broadcast(array: ExAf, shape: Vec<u64>, axes: Vec<usize>) -> NifResult<ExAf> {
+    let exaf_array = array.resource.value()?;
+
+    // Give the tensor the rank of the output by inserting 1s
+    // everywhere it isn't mapped onto by axes.
+    let mut expanded = vec![1; shape.len()];
+    for (axis, out_axis) in axes.into_iter().enumerate() {
+        expanded[out_axis] = array.shape()[axis];
+    }
+
+    let result = catch_af("broadcast", &[&array], || {
+        if shape.len() <= 4 {
+            // Zero sized axes are only broadcast to zero sized axes,
+            // so they are kept as they are.
+            let tiles: Vec<u64> = shape
+                .iter()
+                .zip(expanded.iter())
+                .map(|(&out, &dim)| if dim == 0 { 1 } else { out / dim })
+                .collect();
+
+            let view = dim_from_shape(&expanded);
+            let tiles = dim_from_shape(&tiles);
+
+            map_array!(exaf_array, a => broadcast_array(a, view, tiles))
+        } else {
+            // Folded axes can't be tiled on their own, so tile one
+            // axis at a time instead.
+            let mut broadcasted = exaf_array.clone();
+
+            for (axis, size) in shape.iter().enumerate() {
+                if expanded[axis] != *size {
+                    let view = axis_view(&expanded, axis);
+                    let tiles = Dim4::new(&[1, *size, 1, 1]);
+
+                    broadcasted = map_array!(broadcasted, a => broadcast_array(a, view, tiles));
+                    expanded[axis] = *size;
+                }
+            }
+
+            apply_function_array!(broadcasted, moddims, dim_from_shape(&shape))
+        }
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+fn broadcast_array<T: HasAfEnum>(array: &Array<T>, view: Dim4, tiles: Dim4) -> Array<T> {
+    arrayfire::tile(&arrayfire::moddims(array, view), tiles)
+}
+
+#[rustler::nif]
+pub fn reshape(array: ExAf, shape: Vec<u64>) -> NifResult<ExAf> {
+    let dim = dim_from_shape(&shape);
+    let exaf_array = array.resource.value()?;
+
+    let result = catch_af("reshape", &[&array], || {
+        apply_function_array!(exaf_array, moddims, dim)
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+#[rustler::nif]
+pub fn transpose(array: ExAf, axes: Vec<usize>) -> NifResult<ExAf> {
+    let shape = axes.iter().map(|&axis| array.shape()[axis]).collect();
+    let exaf_array = array.resource.value()?;
+
+    let result = catch_af("transpose", &[&array], || {
+        permute(&exaf_array, array.shape(), &axes)
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+#[rustler::nif]
+pub fn concatenate(arrays: Vec<ExAf>, axis: usize, dtype: String) -> NifResult<ExAf> {
+    let dtype = dtype_from_string(dtype)?;
+    let exaf_arrays = arrays
+        .iter()
+        .map(|array| array.resource.value())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut shape = arrays[0].shape().to_vec();
+    shape[axis] = arrays.iter().map(|array| array.shape()[axis]).sum();
+
+    let inputs: Vec<&ExAf> = arrays.iter().collect();
+
+    let result = try_catch_af("concatenate", &inputs, || {
+        // Every array is joined along dim 1 of its view of the axis.
+        let views: Vec<ExAfArray> = arrays
+            .iter()
+            .zip(exaf_arrays.iter())
+            .map(|(array, exaf_array)| {
+                let view = axis_view(array.shape(), axis);
+                let exaf_array = if exaf_array.dtype() == dtype {
+                    exaf_array.clone()
+                } else {
+                    cast(exaf_array, dtype)
+                };
+
+                apply_function_array!(exaf_array, moddims, view)
+            })
+            .collect();
+
+        let joined =
+            join_arrays!(views, 1, U8, U16, U32, U64, S16, S32, S64, F16, F32, F64, C64, C128)?;
+
+        Ok(apply_function_array!(
+            joined,
+            moddims,
+            dim_from_shape(&shape)
+        ))
+    })?;
+
+    Ok(ExAf::from_exaf_array(result, shape))
+}
+
+/// Joins any number of arrays, join_many only takes up to 10
+/// arrays at a time.
+fn join_chunks<T: HasAfEnum>(arrays: Vec<&Array<T>>, dim: i32) -> Array<T> {
+    if arrays.len() == 1 {
+        return arrays[0].clone();
+    }
+
+    let joined: Vec<Array<T>> = arrays
+        .chunks(10)
+        .map(|chunk| match chunk {
+            [array] => (*array).clone(),
+            _ => arrayfire::join_many(dim, chunk.to_vec()),
+        })
+        .collect();
+
+    join_chunks(joined.iter().collect(), dim)
+}
+
+/// Reorders the axes of an array with the given logical shape, so
+/// that axis i of the result is axis axes[i] of the input.
+fn permute(array: &ExAfArray, shape: &[u64], axes: &[usize]) -> ExAfArray {
+    let out_shape: Vec<u64> = axes.iter().map(|&axis| shape[axis]).collect();
+    let out_dims = dim_from_shape(&out_shape);
+    let (shape, axes) = coalesce_axes(shape, axes);
+
+    // Only size one axes move, the data is already in order.
+    if axes.iter().enumerate().all(|(i, &axis)| i == axis) {
+        return apply_function_array!(array, moddims, out_dims);
+    }
+
+    let permuted = if axes.len() <= 4 {
+        // Nx axis i is ArrayFire dim rank - 1 - i.
+        let rank = axes.len();
+        let mut dims = [0, 1, 2, 3];
+        for (dim, af_dim) in dims.iter_mut().enumerate().take(rank) {
+            *af_dim = (rank - 1 - axes[rank - 1 - dim]) as u64;
+        }
+
+        let viewed = apply_function_array!(array, moddims, dim_from_shape(&shape));
+
+        map_array!(viewed, a => arrayfire::reorder_v2(a, dims[0], dims[1], Some(vec![dims[2], dims[3]])))
+    } else {
+        let indices = permutation_indices(&shape, &axes);
+
+        map_array!(array, a => arrayfire::lookup(&arrayfire::flat(a), &indices, 0))
+    };
+
+    apply_function_array!(permuted, moddims, out_dims)
+}
+
+/// Drops size one axes and merges axes that stay next to each
+/// other, which often brings the rank down to what ArrayFire's
+/// reorder supports.
+fn coalesce_axes(shape: &[u64], axes: &[usize]) -> (Vec<u64>, Vec<usize>) {
+    let kept: Vec<usize> = (0..shape.len()).filter(|&axis| shape[axis] != 1).collect();
+    let position = |axis: usize| kept.iter().position(|&kept| kept == axis).unwrap();
+
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for axis in axes.iter().filter(|&&axis| shape[axis] != 1) {
+        let axis = position(*axis);
+
+        match groups.last_mut() {
+            Some(group) if group[group.len() - 1] + 1 == axis => group.push(axis),
+            _ => groups.push(vec![axis]),
+        }
+    }
+
+    // Groups in the order they appear in the input.
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    order.sort_by_key(|&group| groups[group][0]);
+
+    let coalesced_shape = order
+        .iter()
+        .map(|&group| {
+            groups[group]
+                .iter()
+                .map(|&axis| shape[kept[axis]])
+                .product()
+        })
+        .collect();
+    let coalesced_axes = (0..groups.len())
+        .map(|group| order.iter().position(|&other| other == group).unwrap())
+        .collect();
+
+    (coalesced_shape, coalesced_axes)
+}
+
+/// The linear input index of every element of the permuted array,
+/// in row-major order.
+fn permutation_indices(shape: &[u64], axes: &[usize]) -> Array<u64> {
+    let strides = row_major_strides(shape);
+    let out_shape: Vec<u64> = axes.iter().map(|&axis| shape[axis]).collect();
+    let steps: Vec<u64> = axes.iter().map(|&axis| strides[axis]).collect();
+
+    strided_indices(0, &out_shape, &steps)
+}
+
+// Type
+
+#[rustler::nif]
+pub fn as_type(array: ExAf, dtype: String) -> NifResult<ExAf> {
+    let dtype = dtype_from_string(dtype)?;
+    let exaf_array = array.resource.value()?;
+
+    let result = catch_af("as_type", &[&array], || cast(&exaf_array, dtype))?;
+
+    Ok(ExAf::from_exaf_array(result, array.shape().to_vec()))
+}
+
+fn cast(exaf_array: &ExAfArray, dtype: ExAfDType) -> ExAfArray {
+    if exaf_array.dtype() == dtype {
+        return exaf_array.clone();
+    }
+
+    match (exaf_array, dtype) {
+        (ExAfArray::C64(a), ExAfDType::C128) => ExAfArray::C128(a.cast::<Complex64>()),
+        (ExAfArray::C128(a), ExAfDType::C64) => ExAfArray::C64(a.cast::<Complex32>()),
+        // ArrayFire can't cast complex arrays to real ones, so their
+        // real part is cast instead, which is what Nx does.
+        (ExAfArray::C64(a), _) => cast(&ExAfArray::F32(arrayfire::real(a)), dtype),
+        (ExAfArray::C128(a), _) => cast(&ExAfArray::F64(arrayfire::real(a)), dtype),
+        _ => cast_real(exaf_array, dtype),
+    }
+}
+
+fn cast_real(exaf_array: &ExAfArray, dtype: ExAfDType) -> ExAfArray {
+    match dtype {
+        ExAfDType::U8 => ExAfArray::U8(apply_generic_method_array!(exaf_array, cast, u8,)),
+        ExAfDType::U16 => ExAfArray::U16(apply_generic_method_array!(exaf_array, cast, u16,)),
+        ExAfDType::U32 => ExAfArray::U32(apply_generic_method_array!(exaf_array, cast, u32,)),
+        ExAfDType::U64 => ExAfArray::U64(apply_generic_method_array!(exaf_array, cast, u64,)),
+        ExAfDType::S16 => ExAfArray::S16(apply_generic_method_array!(exaf_array, cast, i16,)),
+        ExAfDType::S32 => ExAfArray::S32(apply_generic_method_array!(exaf_array, cast, i32,)),
+        ExAfDType::S64 => ExAfArray::S64(apply_generic_method_array!(exaf_array, cast, i64,)),
+        ExAfDType::F16 => ExAfArray::F16(apply_generic_method_array!(exaf_array, cast, f16,)),
+        ExAfDType::F32 => ExAfArray::F32(apply_generic_method_array!(exaf_array, cast, f32,)),
+        ExAfDType::F64 => ExAfArray::F64(apply_generic_method_array!(exaf_array, cast, f64,)),
+        ExAfDType::C64 => ExAfArray::C64(apply_generic_method_array!(exaf_array, cast, Complex32,)),
+        ExAfDType::C128 => {
+            ExAfArray::C128(apply_generic_method_array!(exaf_array, cast, Complex64,))
+        }
+    }
+}
+
+// Helpers
+
+pub(crate) fn dtype_from_string(dtype: String) -> Result<ExAfDType, ExAfError> {
     match dtype.as_str() {
-        "u8" => ExAfDType::U8,
-        "u16" => ExAfDType::U16,
-        "u32" => ExAfDType::U32,
-        "u64" => ExAfDType::U64,
-        "s16" => ExAfDType::S16,
-        "s32" => ExAfDType::S32,
-        "s64" => ExAfDType::S64,
-        "f16" => ExAfDType::F16,
-        "f32" => ExAfDType::F32,
-        "f64" => ExAfDType::F64,
-        "c64" => ExAfDType::C64,
-        "c128" => ExAfDType::C128,
-        _ => unimplemented!(),
+        "u8" => Ok(ExAfDType::U8),
+        "u16" => Ok(ExAfDType::U16),
+        "u32" => Ok(ExAfDType::U32),
+        "u64" => Ok(ExAfDType::U64),
+        "s16" => Ok(ExAfDType::S16),
+        "s32" => Ok(ExAfDType::S32),
+        "s64" => Ok(ExAfDType::S64),
+        "f16" => Ok(ExAfDType::F16),
+        "f32" => Ok(ExAfDType::F32),
+        "f64" => Ok(ExAfDType::F64),
+        "c64" => Ok(ExAfDType::C64),
+        "c128" => Ok(ExAfDType::C128),
+        _ => Err(ExAfError::InvalidDType(dtype)),
     }
 }
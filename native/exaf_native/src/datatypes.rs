@@ -1,3 +1,5 @@
+use crate::error::ExAfError;
+
 use arrayfire::{Array, Dim4};
 use half::f16;
 use num_complex::{Complex, Complex32, Complex64};
@@ -5,7 +7,7 @@ use rustler::resource::ResourceArc;
 use rustler::NifStruct;
 use std::sync::RwLock;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum ExAfDType {
     U8,
     U16,
@@ -31,6 +33,23 @@ impl ExAfDType {
             ExAfDType::C128 => 16,
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExAfDType::U8 => "u8",
+            ExAfDType::U16 => "u16",
+            ExAfDType::U32 => "u32",
+            ExAfDType::U64 => "u64",
+            ExAfDType::S16 => "s16",
+            ExAfDType::S32 => "s32",
+            ExAfDType::S64 => "s64",
+            ExAfDType::F16 => "f16",
+            ExAfDType::F32 => "f32",
+            ExAfDType::F64 => "f64",
+            ExAfDType::C64 => "c64",
+            ExAfDType::C128 => "c128",
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -50,7 +69,11 @@ pub enum ExAfArray {
 }
 
 impl ExAfArray {
-    pub fn from_slice(slice: &[u8], dim: Dim4, dtype: ExAfDType) -> Self {
+    /// Builds an array from the row-major binary of an Nx tensor of
+    /// the given shape. See the Layout section for the convention.
+    pub fn from_slice(slice: &[u8], shape: &[u64], dtype: ExAfDType) -> Self {
+        let dim = dim_from_shape(shape);
+
         match dtype {
             ExAfDType::U8 => ExAfArray::U8(Array::new(slice, dim)),
             ExAfDType::U16 => {
@@ -111,6 +134,9 @@ impl ExAfArray {
         }
     }
 
+    /// Copies the array to the host as the row-major binary Nx
+    /// expects. Thanks to the reversed dimensions, this is the
+    /// column-major buffer ArrayFire already holds.
     pub fn to_vec(&self) -> Vec<u8> {
         let nelements = apply_method_array!(self, elements,);
 
@@ -338,7 +364,7 @@ pub(crate) use apply_generic_method_array;
 #[macro_export]
 macro_rules! apply_function_array {
     ($self:ident, $function:ident, $($args:expr),*) => {
-        ExAf::from_exaf_array( match $self {
+        match $self {
             ExAfArray::U8(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
             ExAfArray::U16(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
             ExAfArray::U32(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
@@ -350,8 +376,8 @@ macro_rules! apply_function_array {
             ExAfArray::F32(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
             ExAfArray::F64(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
             ExAfArray::C64(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
-            ExAfArray::C128(ref a) =>arrayfire::$function(a, $($args), *).to_exaf_array(),
-        })
+            ExAfArray::C128(ref a) => arrayfire::$function(a, $($args), *).to_exaf_array(),
+        }
     };
 }
 
@@ -360,7 +386,7 @@ pub(crate) use apply_function_array;
 #[macro_export]
 macro_rules! apply_generic_function_array {
     ($function:ident, $dtype:ident, $($args:expr),*) => {
-        ExAf::from_exaf_array(match $dtype {
+        match $dtype {
             ExAfDType::U8 => arrayfire::$function::<u8>($($args), *).to_exaf_array(),
             ExAfDType::U16 => arrayfire::$function::<u16>($($args), *).to_exaf_array(),
             ExAfDType::U32 => arrayfire::$function::<u32>($($args), *).to_exaf_array(),
@@ -373,13 +399,161 @@ macro_rules! apply_generic_function_array {
             ExAfDType::F64 => arrayfire::$function::<f64>($($args), *).to_exaf_array(),
             ExAfDType::C64 => arrayfire::$function::<Complex32>($($args), *).to_exaf_array(),
             ExAfDType::C128 => arrayfire::$function::<Complex64>($($args), *).to_exaf_array(),
-        })
+        }
     };
 }
 
 pub(crate) use apply_generic_function_array;
 
-pub struct ExAfRef(pub RwLock<ExAfArray>);
+#[macro_export]
+macro_rules! map_array {
+    ($self:expr, $a:ident => $body:expr) => {
+        match $self {
+            ExAfArray::U8(ref $a) => $body.to_exaf_array(),
+            ExAfArray::U16(ref $a) => $body.to_exaf_array(),
+            ExAfArray::U32(ref $a) => $body.to_exaf_array(),
+            ExAfArray::U64(ref $a) => $body.to_exaf_array(),
+            ExAfArray::S16(ref $a) => $body.to_exaf_array(),
+            ExAfArray::S32(ref $a) => $body.to_exaf_array(),
+            ExAfArray::S64(ref $a) => $body.to_exaf_array(),
+            ExAfArray::F16(ref $a) => $body.to_exaf_array(),
+            ExAfArray::F32(ref $a) => $body.to_exaf_array(),
+            ExAfArray::F64(ref $a) => $body.to_exaf_array(),
+            ExAfArray::C64(ref $a) => $body.to_exaf_array(),
+            ExAfArray::C128(ref $a) => $body.to_exaf_array(),
+        }
+    };
+}
+
+pub(crate) use map_array;
+
+#[macro_export]
+macro_rules! match_array {
+    ($self:expr, $a:ident => $body:expr) => {
+        match $self {
+            ExAfArray::U8(ref $a) => $body,
+            ExAfArray::U16(ref $a) => $body,
+            ExAfArray::U32(ref $a) => $body,
+            ExAfArray::U64(ref $a) => $body,
+            ExAfArray::S16(ref $a) => $body,
+            ExAfArray::S32(ref $a) => $body,
+            ExAfArray::S64(ref $a) => $body,
+            ExAfArray::F16(ref $a) => $body,
+            ExAfArray::F32(ref $a) => $body,
+            ExAfArray::F64(ref $a) => $body,
+            ExAfArray::C64(ref $a) => $body,
+            ExAfArray::C128(ref $a) => $body,
+        }
+    };
+}
+
+pub(crate) use match_array;
+
+// Pairs of arrays with different dtypes can be passed to NIFs
+// directly, so the pair macros return an error for them.
+
+#[macro_export]
+macro_rules! map_array_pair {
+    ($left:expr, $right:expr, ($a:ident, $b:ident) => $body:expr) => {
+        match ($left, $right) {
+            (ExAfArray::U8(ref $a), ExAfArray::U8(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::U16(ref $a), ExAfArray::U16(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::U32(ref $a), ExAfArray::U32(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::U64(ref $a), ExAfArray::U64(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::S16(ref $a), ExAfArray::S16(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::S32(ref $a), ExAfArray::S32(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::S64(ref $a), ExAfArray::S64(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::F16(ref $a), ExAfArray::F16(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::F32(ref $a), ExAfArray::F32(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::F64(ref $a), ExAfArray::F64(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::C64(ref $a), ExAfArray::C64(ref $b)) => Ok($body.to_exaf_array()),
+            (ExAfArray::C128(ref $a), ExAfArray::C128(ref $b)) => Ok($body.to_exaf_array()),
+            (ref left, ref right) => Err(mismatched_dtypes(left, right)),
+        }
+    };
+}
+
+pub(crate) use map_array_pair;
+
+#[macro_export]
+macro_rules! match_array_pair {
+    ($left:expr, $right:expr, ($a:ident, $b:ident) => $body:expr) => {
+        match ($left, $right) {
+            (ExAfArray::U8(ref $a), ExAfArray::U8(ref $b)) => Ok($body),
+            (ExAfArray::U16(ref $a), ExAfArray::U16(ref $b)) => Ok($body),
+            (ExAfArray::U32(ref $a), ExAfArray::U32(ref $b)) => Ok($body),
+            (ExAfArray::U64(ref $a), ExAfArray::U64(ref $b)) => Ok($body),
+            (ExAfArray::S16(ref $a), ExAfArray::S16(ref $b)) => Ok($body),
+            (ExAfArray::S32(ref $a), ExAfArray::S32(ref $b)) => Ok($body),
+            (ExAfArray::S64(ref $a), ExAfArray::S64(ref $b)) => Ok($body),
+            (ExAfArray::F16(ref $a), ExAfArray::F16(ref $b)) => Ok($body),
+            (ExAfArray::F32(ref $a), ExAfArray::F32(ref $b)) => Ok($body),
+            (ExAfArray::F64(ref $a), ExAfArray::F64(ref $b)) => Ok($body),
+            (ExAfArray::C64(ref $a), ExAfArray::C64(ref $b)) => Ok($body),
+            (ExAfArray::C128(ref $a), ExAfArray::C128(ref $b)) => Ok($body),
+            (ref left, ref right) => Err(mismatched_dtypes(left, right)),
+        }
+    };
+}
+
+pub(crate) use match_array_pair;
+
+pub(crate) fn mismatched_dtypes(left: &ExAfArray, right: &ExAfArray) -> ExAfError {
+    ExAfError::InvalidDType(format!(
+        "{} and {}, arrays must have the same dtype",
+        left.dtype().name(),
+        right.dtype().name()
+    ))
+}
+
+// Layout
+//
+// Nx tensors are row-major while ArrayFire arrays are column-major.
+// Rather than moving data around, an Nx shape is given to ArrayFire
+// with its dimensions reversed: the row-major binary of a tensor of
+// shape {a, b, c} is exactly the column-major buffer of an array
+// with dims [c, b, a, 1].
+//
+// This means that Nx axis i of a rank r tensor is ArrayFire dim
+// r - 1 - i, which every axis-aware NIF has to account for.
+//
+// ArrayFire arrays have at most 4 dims, so the leading axes of
+// tensors with a higher rank are folded into the last dim. As
+// folding doesn't move any data either, each ExAfRef keeps the
+// logical Nx shape next to the array.
+
+pub fn dim_from_shape(shape: &[u64]) -> Dim4 {
+    let mut dims = [1; 4];
+    for (dim, size) in dims.iter_mut().zip(shape.iter().rev()) {
+        *dim = *size;
+    }
+
+    // Fold all the leading axes into the last dim
+    if shape.len() > 4 {
+        dims[3] = shape[..shape.len() - 3].iter().product();
+    }
+
+    Dim4::new(&dims)
+}
+
+/// Returns the dims of a 3-dimensional view of the shape with the
+/// given axis as dim 1, all the axes after it folded into dim 0 and
+/// all the axes before it folded into dim 2.
+///
+/// Any op along a single axis can work on this view, regardless of
+/// the rank of the tensor.
+pub fn axis_view(shape: &[u64], axis: usize) -> Dim4 {
+    let inner = shape[axis + 1..].iter().product();
+    let outer = shape[..axis].iter().product();
+
+    Dim4::new(&[inner, shape[axis], outer, 1])
+}
+
+pub struct ExAfRef {
+    /// Holds `None` once the array has been deallocated.
+    array: RwLock<Option<ExAfArray>>,
+    pub shape: Vec<u64>,
+}
 
 #[derive(NifStruct)]
 #[module = "ExAF.Backend"]
@@ -388,31 +562,50 @@ pub struct ExAf {
 }
 
 impl ExAfRef {
-    pub fn from_exaf_array(array: ExAfArray) -> Self {
-        Self(RwLock::new(array))
+    pub fn from_exaf_array(array: ExAfArray, shape: Vec<u64>) -> Self {
+        Self {
+            array: RwLock::new(Some(array)),
+            shape,
+        }
     }
 
-    pub fn from_slice(slice: &[u8], dim: Dim4, dtype: ExAfDType) -> Self {
-        Self(RwLock::new(ExAfArray::from_slice(slice, dim, dtype)))
+    pub fn from_slice(slice: &[u8], shape: Vec<u64>, dtype: ExAfDType) -> Self {
+        let array = ExAfArray::from_slice(slice, &shape, dtype);
+        Self::from_exaf_array(array, shape)
     }
 
-    pub fn value(&self) -> ExAfArray {
-        match self.0.try_read() {
-            Ok(reference) => reference.clone(),
-            Err(_) => unreachable!(),
+    pub fn value(&self) -> Result<ExAfArray, ExAfError> {
+        match self.array.read() {
+            Ok(reference) => match *reference {
+                Some(ref array) => Ok(array.clone()),
+                None => Err(ExAfError::AlreadyDeallocated),
+            },
+            Err(_) => Err(ExAfError::LockPoisoned),
+        }
+    }
+
+    pub fn deallocate(&self) -> Result<Option<ExAfArray>, ExAfError> {
+        match self.array.write() {
+            Ok(mut reference) => Ok(reference.take()),
+            Err(_) => Err(ExAfError::LockPoisoned),
         }
     }
 }
 
 impl ExAf {
-    pub fn from_exaf_array(array: ExAfArray) -> Self {
+    pub fn from_exaf_array(array: ExAfArray, shape: Vec<u64>) -> Self {
         Self {
-            resource: ResourceArc::new(ExAfRef::from_exaf_array(array)),
+            resource: ResourceArc::new(ExAfRef::from_exaf_array(array, shape)),
         }
     }
-    pub fn from_slice(slice: &[u8], dim: Dim4, dtype: ExAfDType) -> Self {
+
+    pub fn from_slice(slice: &[u8], shape: Vec<u64>, dtype: ExAfDType) -> Self {
         Self {
-            resource: ResourceArc::new(ExAfRef::from_slice(slice, dim, dtype)),
+            resource: ResourceArc::new(ExAfRef::from_slice(slice, shape, dtype)),
         }
     }
+
+    pub fn shape(&self) -> &[u64] {
+        &self.resource.shape
+    }
 }
@@ -0,0 +1,140 @@
+use crate::datatypes::ExAf;
+
+use arrayfire::AfError;
+use rustler::{Encoder, Env, Term};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+mod atoms {
+    rustler::atoms! {
+        already_deallocated,
+        singular_matrix
+    }
+}
+
+pub enum ExAfError {
+    AlreadyDeallocated,
+    LockPoisoned,
+    BinaryAllocation,
+    InvalidShape(String),
+    InvalidDType(String),
+    InvalidRandomEngine(String),
+    NotPositiveDefinite,
+    SingularMatrix,
+    NotConverged(u64),
+    ArrayFire {
+        op: &'static str,
+        error: AfError,
+        shapes: Vec<Vec<u64>>,
+        dtypes: Vec<&'static str>,
+    },
+}
+
+impl fmt::Display for ExAfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExAfError::AlreadyDeallocated => write!(f, "array has already been deallocated"),
+            ExAfError::LockPoisoned => write!(f, "array lock was poisoned by a panicking NIF"),
+            ExAfError::BinaryAllocation => write!(f, "could not allocate binary"),
+            ExAfError::InvalidShape(message) => write!(f, "invalid shape: {}", message),
+            ExAfError::InvalidDType(dtype) => write!(f, "unsupported dtype: {}", dtype),
+            ExAfError::InvalidRandomEngine(engine_type) => {
+                write!(f, "unsupported random engine: {}", engine_type)
+            }
+            ExAfError::NotPositiveDefinite => write!(f, "matrix is not positive definite"),
+            ExAfError::SingularMatrix => write!(f, "can't solve for singular matrix"),
+            ExAfError::NotConverged(max_iter) => {
+                write!(f, "eigenvalues did not converge in {} iterations", max_iter)
+            }
+            ExAfError::ArrayFire {
+                op,
+                error,
+                shapes,
+                dtypes,
+            } => write!(
+                f,
+                "ArrayFire error in {}: {} (shapes: {:?}, dtypes: {:?})",
+                op, error, shapes, dtypes
+            ),
+        }
+    }
+}
+
+impl Encoder for ExAfError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            ExAfError::AlreadyDeallocated => atoms::already_deallocated().encode(env),
+            ExAfError::SingularMatrix => atoms::singular_matrix().encode(env),
+            error => error.to_string().encode(env),
+        }
+    }
+}
+
+// Encoded as {:error, reason} by rustler.
+impl From<ExAfError> for rustler::Error {
+    fn from(error: ExAfError) -> Self {
+        rustler::Error::Term(Box::new(error))
+    }
+}
+
+// ArrayFire Error Handling
+//
+// arrayfire-rust reports errors through a global callback, which
+// panics by default. We register our own callback, which unwinds
+// with the AfError itself so that catch_af can recover it and
+// attach the context of the failing NIF.
+
+pub fn handle_af_error(error: AfError) {
+    if error != AfError::SUCCESS {
+        panic::panic_any(error);
+    }
+}
+
+/// Installs the ArrayFire error callback and the panic hook. The
+/// panic hook is process wide, so this is only called once, from
+/// the NIF library's load.
+pub fn register_af_error_handler() {
+    arrayfire::register_error_handler(arrayfire::Callback::new(handle_af_error));
+
+    // Don't print ArrayFire errors as panics, they are returned
+    // to Elixir instead. Every other panic still goes through the
+    // hook that was installed before ours.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if info.payload().downcast_ref::<AfError>().is_none() {
+            default_hook(info)
+        }
+    }));
+}
+
+pub fn catch_af<T, F>(op: &'static str, inputs: &[&ExAf], function: F) -> Result<T, ExAfError>
+where
+    F: FnOnce() -> T,
+{
+    panic::catch_unwind(AssertUnwindSafe(function)).map_err(|payload| {
+        match payload.downcast::<AfError>() {
+            Ok(error) => ExAfError::ArrayFire {
+                op,
+                error: *error,
+                shapes: inputs.iter().map(|input| input.shape().to_vec()).collect(),
+                dtypes: inputs.iter().map(|input| dtype_name(input)).collect(),
+            },
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    })
+}
+
+/// Like catch_af, for functions that return errors of their own.
+pub fn try_catch_af<T, F>(op: &'static str, inputs: &[&ExAf], function: F) -> Result<T, ExAfError>
+where
+    F: FnOnce() -> Result<T, ExAfError>,
+{
+    catch_af(op, inputs, function)?
+}
+
+fn dtype_name(input: &ExAf) -> &'static str {
+    match input.resource.value() {
+        Ok(array) => array.dtype().name(),
+        Err(_) => "deallocated",
+    }
+}
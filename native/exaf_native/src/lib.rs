@@ -2,12 +2,17 @@ use rustler::{Env, Term};
 
 mod array;
 mod datatypes;
+mod error;
+mod random;
 
 use array::*;
 use datatypes::ExAfRef;
+use random::*;
 
 fn load(env: Env, _info: Term) -> bool {
     rustler::resource!(ExAfRef, env);
+    rustler::resource!(ExAfRandomEngineRef, env);
+    error::register_af_error_handler();
     true
 }
 
@@ -50,6 +55,13 @@ rustler::init!(
         // Elementwise - Shifts
         left_shift,
         right_shift,
+        // Elementwise - Bitwise
+        bitwise_and,
+        bitwise_or,
+        bitwise_xor,
+        bitwise_not,
+        population_count,
+        count_leading_zeros,
         // Elementwise - Trignomentry
         sin,
         cos,
@@ -78,10 +90,67 @@ rustler::init!(
         ceil,
         real,
         imag,
+        // Aggregates
+        sum,
+        product,
+        reduce_max,
+        reduce_min,
+        all,
+        any,
+        argmax,
+        argmin,
+        cumulative_sum,
+        cumulative_product,
+        cumulative_max,
+        cumulative_min,
+        window_sum,
+        window_product,
+        window_max,
+        window_min,
+        window_scatter_max,
+        window_scatter_min,
+        // Sorting
+        sort,
+        argsort,
+        top_k,
+        // Linear Algebra
+        dot,
+        lu,
+        qr,
+        cholesky,
+        triangular_solve,
+        solve,
+        svd,
+        eigh,
+        invert,
+        determinant,
+        // Indexing
+        slice,
+        put_slice,
+        pad,
+        take,
+        take_along_axis,
+        gather,
+        indexed_add,
+        indexed_put,
+        // Convolution
+        conv,
+        // FFT
+        fft,
+        ifft,
         // Shape
+        broadcast,
         reshape,
+        transpose,
+        concatenate,
         // Type
-        as_type
+        as_type,
+        // Random
+        random_engine,
+        random_engine_seed,
+        random_engine_set_seed,
+        random_uniform,
+        random_normal
     ],
     load = load
 );
@@ -0,0 +1,119 @@
+use crate::array::dtype_from_string;
+use crate::datatypes::*;
+use crate::error::{catch_af, try_catch_af, ExAfError};
+
+use arrayfire::{RandomEngine, RandomEngineType};
+use half::f16;
+use rustler::{Atom, NifResult, NifStruct, ResourceArc};
+use std::sync::{Mutex, MutexGuard};
+
+mod atoms {
+    rustler::atoms! {
+        ok
+    }
+}
+
+pub struct ExAfRandomEngineRef {
+    /// The engine's state advances every time numbers are
+    /// generated, so generating takes the lock.
+    engine: Mutex<RandomEngine>,
+}
+
+#[derive(NifStruct)]
+#[module = "ExAF.Random.Engine"]
+pub struct ExAfRandomEngine {
+    pub resource: ResourceArc<ExAfRandomEngineRef>,
+}
+
+impl ExAfRandomEngineRef {
+    fn lock(&self) -> Result<MutexGuard<RandomEngine>, ExAfError> {
+        self.engine.lock().map_err(|_| ExAfError::LockPoisoned)
+    }
+}
+
+// Public API
+
+// Engines
+
+#[rustler::nif]
+pub fn random_engine(engine_type: String, seed: u64) -> NifResult<ExAfRandomEngine> {
+    let engine_type = engine_type_from_string(engine_type)?;
+    let engine = catch_af("random_engine", &[], || {
+        RandomEngine::new(engine_type, Some(seed))
+    })?;
+
+    Ok(ExAfRandomEngine {
+        resource: ResourceArc::new(ExAfRandomEngineRef {
+            engine: Mutex::new(engine),
+        }),
+    })
+}
+
+#[rustler::nif]
+pub fn random_engine_seed(engine: ExAfRandomEngine) -> NifResult<u64> {
+    let random_engine = engine.resource.lock()?;
+    let seed = catch_af("random_engine_seed", &[], || random_engine.get_seed())?;
+
+    Ok(seed)
+}
+
+#[rustler::nif]
+pub fn random_engine_set_seed(engine: ExAfRandomEngine, seed: u64) -> NifResult<Atom> {
+    let mut random_engine = engine.resource.lock()?;
+    catch_af("random_engine_set_seed", &[], || {
+        random_engine.set_seed(seed)
+    })?;
+
+    Ok(atoms::ok())
+}
+
+// Generation
+
+macro_rules! random_op {
+    ($op_name:ident, $af_op:ident) => {
+        #[rustler::nif]
+        pub fn $op_name(
+            engine: ExAfRandomEngine,
+            shape: Vec<u64>,
+            dtype: String,
+        ) -> NifResult<ExAf> {
+            let dtype = dtype_from_string(dtype)?;
+            let random_engine = engine.resource.lock()?;
+            let dims = dim_from_shape(&shape);
+
+            let result = try_catch_af(stringify!($op_name), &[], || match dtype {
+                // f16 numbers are generated as f32, which every
+                // device supports, and then converted.
+                ExAfDType::F16 => Ok(arrayfire::$af_op::<f32>(dims, &random_engine)
+                    .cast::<f16>()
+                    .to_exaf_array()),
+                ExAfDType::F32 => {
+                    Ok(arrayfire::$af_op::<f32>(dims, &random_engine).to_exaf_array())
+                }
+                ExAfDType::F64 => {
+                    Ok(arrayfire::$af_op::<f64>(dims, &random_engine).to_exaf_array())
+                }
+                dtype => Err(ExAfError::InvalidDType(format!(
+                    "{}, random numbers are generated as floats",
+                    dtype.name()
+                ))),
+            })?;
+
+            Ok(ExAf::from_exaf_array(result, shape))
+        }
+    };
+}
+
+random_op!(random_uniform, random_uniform);
+random_op!(random_normal, random_normal);
+
+// Helpers
+
+fn engine_type_from_string(engine_type: String) -> Result<RandomEngineType, ExAfError> {
+    match engine_type.as_str() {
+        "philox" => Ok(RandomEngineType::PHILOX_4X32_10),
+        "threefry" => Ok(RandomEngineType::THREEFRY_2X32_16),
+        "mersenne" => Ok(RandomEngineType::MERSENNE_GP11213),
+        _ => Err(ExAfError::InvalidRandomEngine(engine_type)),
+    }
+}
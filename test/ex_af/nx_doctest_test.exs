@@ -20,30 +20,7 @@ defmodule ExAF.NxDoctestTest do
     |> Keyword.keys()
     |> then(&Keyword.drop(@nx_funcs, &1))
 
-  temporarily_broken_doctests = [
-    # ExAF has not implemented broadcast/4
-    add: 2,
-    # ExAF has not implemented broadcast/4
-    remainder: 2,
-    # ExAF has not implemented broadcast/4
-    power: 2,
-    # ExAF has not implemented broadcast/4
-    atan2: 2,
-    # ExAF has not implemented broadcast/4
-    equal: 2,
-    # ExAF has not implemented broadcast/4
-    greater: 2,
-    # ExAF has not implemented broadcast/4
-    less: 2,
-    # ExAF has not implemented broadcast/4
-    greater_equal: 2,
-    # ExAF has not implemented broadcast/4
-    less_equal: 2,
-    # ExAF has not implemented broadcast/4
-    logical_and: 2,
-    # ExAF has not implemented broadcast/4
-    logical_or: 2
-  ]
+  temporarily_broken_doctests = []
 
   inherently_unsupported_doctests = [
     # ExAF/Arrayfire does not support s8
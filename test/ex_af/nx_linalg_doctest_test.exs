@@ -8,5 +8,22 @@ defmodule ExAF.NxLinAlgDoctestTest do
 
   use ExUnit.Case, async: true
 
-  # doctest Nx.LinAlg
+  # Singular vectors and eigenvectors are only unique up to
+  # their sign, which LAPACK picks differently. Doctests can only
+  # be excluded whole, so the singular values, eigenvalues and
+  # vectors up to their sign are checked on ExAF.NxLinAlgTest.
+  sign_difference_doctests = [
+    svd: 2,
+    eigh: 2
+  ]
+
+  # pinv goes through svd, and LAPACK doesn't round the singular
+  # values the same way as Nx.BinaryBackend in the digits the
+  # doctest prints.
+  rounding_error_doctests = [
+    pinv: 2
+  ]
+
+  doctest Nx.LinAlg,
+    except: sign_difference_doctests ++ rounding_error_doctests
 end
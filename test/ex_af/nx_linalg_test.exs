@@ -0,0 +1,366 @@
+defmodule ExAF.NxLinAlgTest do
+  use ExAF.Case, async: true
+
+  @float_types [{:f, 32}, {:f, 64}]
+
+  describe "lu" do
+    for type <- @float_types do
+      test "reconstructs #{Nx.Type.to_string(type)} matrices" do
+        t = Nx.tensor([[1, 2, 3], [4, 5, 6], [7, 8, 10]], type: unquote(type))
+        {p, l, u} = Nx.LinAlg.lu(t)
+
+        assert_close(p |> Nx.dot(l) |> Nx.dot(u), t)
+      end
+    end
+
+    test "reconstructs rectangular matrices" do
+      t = Nx.tensor([[1, 2, 3], [4, 5, 6]], type: {:f, 32})
+      {p, l, u} = Nx.LinAlg.lu(t)
+
+      assert {p.shape, l.shape, u.shape} == {{2, 2}, {2, 2}, {2, 3}}
+      assert_close(p |> Nx.dot(l) |> Nx.dot(u), t)
+    end
+
+    test "works on batches of matrices" do
+      t = Nx.tensor([[[1, 2], [3, 4]], [[0, 1], [1, 0]], [[2, 0], [0, 2]]])
+      {p, l, u} = Nx.LinAlg.lu(t)
+
+      assert p.shape == {3, 2, 2}
+      assert_close(p |> Nx.dot([2], [0], l, [1], [0]) |> Nx.dot([2], [0], u, [1], [0]), t)
+    end
+
+    test "reconstructs complex matrices" do
+      t = Nx.tensor([[Complex.new(1, 1), 2], [3, Complex.new(4, -1)]])
+      {p, l, u} = Nx.LinAlg.lu(t)
+
+      assert_close(p |> Nx.dot(l) |> Nx.dot(u), t)
+    end
+  end
+
+  describe "qr" do
+    for mode <- [:reduced, :complete] do
+      test "#{mode} decomposition of a tall matrix" do
+        t = Nx.tensor([[1, 2], [3, 4], [5, 6]], type: {:f, 32})
+        {q, r} = Nx.LinAlg.qr(t, mode: unquote(mode))
+
+        size = if unquote(mode) == :reduced, do: 2, else: 3
+        assert q.shape == {3, size}
+        assert r.shape == {size, 2}
+        assert_close(Nx.dot(q, r), t)
+        assert_close(Nx.dot(Nx.transpose(q), q), Nx.eye(size))
+      end
+    end
+
+    test "matches Nx.BinaryBackend" do
+      t = Nx.tensor([[12, -51, 4], [6, 167, -68], [-4, 24, -41]], type: {:f, 64})
+
+      assert_close_to_binary_backend(t, &Nx.LinAlg.qr/1)
+    end
+  end
+
+  describe "cholesky" do
+    test "matches Nx.BinaryBackend" do
+      t = Nx.tensor([[4, 12, -16], [12, 37, -43], [-16, -43, 98]], type: {:f, 64})
+
+      assert_close_to_binary_backend(t, &Nx.LinAlg.cholesky/1)
+    end
+
+    test "raises on matrices that are not positive definite" do
+      assert_raise RuntimeError, "matrix is not positive definite", fn ->
+        Nx.LinAlg.cholesky(Nx.tensor([[1.0, 2.0], [2.0, 1.0]]))
+      end
+    end
+  end
+
+  describe "triangular_solve" do
+    for left_side <- [true, false], lower <- [true, false], transform_a <- [:none, :transpose] do
+      test "with left_side: #{left_side}, lower: #{lower} and transform_a: #{transform_a}" do
+        a = Nx.tensor([[3, 0, 0], [2, 1, 0], [1, 1, 1]], type: {:f, 64})
+        a = if unquote(lower), do: a, else: Nx.transpose(a)
+        b = Nx.tensor([[4, 2, 4], [2, 1, 3], [1, 1, 2]], type: {:f, 64})
+
+        opts = [
+          left_side: unquote(left_side),
+          lower: unquote(lower),
+          transform_a: unquote(transform_a)
+        ]
+
+        assert_close_to_binary_backend({a, b}, fn {a, b} ->
+          Nx.LinAlg.triangular_solve(a, b, opts)
+        end)
+      end
+    end
+
+    test "with a vector on the right hand side" do
+      a = Nx.tensor([[3, 0, 0], [2, 1, 0], [1, 1, 1]], type: {:f, 32})
+      b = Nx.tensor([4, 2, 4], type: {:f, 32})
+
+      assert_close_to_binary_backend({a, b}, fn {a, b} -> Nx.LinAlg.triangular_solve(a, b) end)
+    end
+  end
+
+  describe "solve" do
+    test "matches Nx.BinaryBackend" do
+      a = Nx.tensor([[1, 3, 2, 1], [2, 1, 0, 0], [1, 0, 1, 0], [1, 1, 1, 1]], type: {:f, 64})
+      b = Nx.tensor([[-3, 1], [0, 2], [2, 3], [-2, 4]], type: {:f, 64})
+
+      assert_close_to_binary_backend({a, b}, fn {a, b} -> Nx.LinAlg.solve(a, b) end)
+    end
+
+    test "with a vector on the right hand side" do
+      a = Nx.tensor([[1, 3, 2, 1], [2, 1, 0, 0], [1, 0, 1, 0], [1, 1, 1, 1]], type: {:f, 64})
+      b = Nx.tensor([-3, 0, 2, -2], type: {:f, 64})
+
+      assert_close_to_binary_backend({a, b}, fn {a, b} -> Nx.LinAlg.solve(a, b) end)
+    end
+
+    test "with complex matrices" do
+      a = Nx.tensor([[Complex.new(1, 1), 2], [3, Complex.new(4, -1)]])
+      b = Nx.tensor([Complex.new(0, 2), 1])
+
+      assert_close(Nx.dot(a, Nx.LinAlg.solve(a, b)), b)
+    end
+
+    test "raises on singular matrices" do
+      a = Nx.tensor([[1, 2], [2, 4]], type: {:f, 32})
+      b = Nx.tensor([1, 2], type: {:f, 32})
+
+      assert_raise ArgumentError, "can't solve for singular matrix", fn ->
+        Nx.LinAlg.solve(a, b)
+      end
+    end
+  end
+
+  describe "svd" do
+    test "reconstructs square matrices" do
+      t = Nx.tensor([[2, 0, 1], [1, 3, 0], [0, 1, 4]], type: {:f, 32})
+      {u, s, vt} = Nx.LinAlg.svd(t)
+
+      assert_close(u |> Nx.multiply(s) |> Nx.dot(vt), t)
+      assert_close(Nx.dot(Nx.transpose(u), u), Nx.eye(3))
+    end
+
+    test "matches the singular values of Nx.BinaryBackend" do
+      t = Nx.tensor([[1, 2, 3], [4, 5, 6], [7, 8, 10], [1, 0, 1]], type: {:f, 64})
+
+      assert_close_to_binary_backend(t, &elem(Nx.LinAlg.svd(&1), 1))
+    end
+
+    test "works on batches of matrices" do
+      t = Nx.tensor([[[3, 0], [0, -2]], [[1, 1], [1, 1]]], type: {:f, 32})
+      {_u, s, _vt} = Nx.LinAlg.svd(t)
+
+      assert_close(s, Nx.tensor([[3.0, 2.0], [2.0, 0.0]]))
+    end
+
+    # The svd doctests, with the singular vectors compared up to
+    # their sign.
+    test "of the doctest matrices" do
+      for t <- [
+            Nx.tensor([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]]),
+            Nx.tensor([[2.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, -1.0], [0.0, 0.0, 0.0]])
+          ] do
+        assert_close_to_binary_backend(
+          t,
+          fn t ->
+            {u, s, vt} = Nx.LinAlg.svd(t)
+            {Nx.abs(u), s, Nx.abs(vt)}
+          end,
+          atol: 1.0e-6
+        )
+      end
+    end
+  end
+
+  describe "pinv" do
+    test "matches Nx.BinaryBackend" do
+      for t <- [
+            Nx.tensor([[1, 2], [3, 4], [5, 6]], type: {:f, 64}),
+            Nx.tensor([[1, 1, 0], [0, 1, 1]], type: {:f, 64}),
+            Nx.tensor([1, 0, 2], type: {:f, 64})
+          ] do
+        assert_close_to_binary_backend(t, &Nx.LinAlg.pinv/1, atol: 1.0e-10)
+      end
+    end
+  end
+
+  describe "eigh" do
+    test "reconstructs symmetric matrices" do
+      t = Nx.tensor([[5, -1, 0], [-1, 3, 2], [0, 2, -4]], type: {:f, 64})
+      {eigenvals, eigenvecs} = Nx.LinAlg.eigh(t)
+
+      reconstructed =
+        eigenvecs
+        |> Nx.multiply(eigenvals)
+        |> Nx.dot(Nx.transpose(eigenvecs))
+
+      assert_close(reconstructed, t)
+    end
+
+    for type <- @float_types do
+      test "matches the #{Nx.Type.to_string(type)} eigenvalues of Nx.BinaryBackend" do
+        t = Nx.tensor([[5, -1, 0], [-1, 3, 2], [0, 2, -4]], type: unquote(type))
+
+        assert_close_to_binary_backend(t, &elem(Nx.LinAlg.eigh(&1, max_iter: 5_000), 0))
+      end
+    end
+
+    # Eigenvectors are only unique up to their sign.
+    test "matches the eigenvectors of Nx.BinaryBackend up to their sign" do
+      t = Nx.tensor([[5, -1, 0], [-1, 3, 2], [0, 2, -4]], type: {:f, 64})
+
+      assert_close_to_binary_backend(t, fn t ->
+        {eigenvals, eigenvecs} = Nx.LinAlg.eigh(t, eps: 1.0e-12)
+        {eigenvals, Nx.abs(eigenvecs)}
+      end)
+    end
+
+    test "keeps small f32 eigenvalues precise" do
+      t = Nx.tensor([[1, 1], [1, 1.00001]], type: {:f, 32})
+
+      assert_close_to_binary_backend(t, &elem(Nx.LinAlg.eigh(&1), 0), atol: 0, rtol: 1.0e-3)
+    end
+
+    # Nx.BinaryBackend's unshifted QR iteration doesn't converge
+    # for eigenvalues of equal magnitude and opposite sign, so the
+    # eigenvalues are compared in sorted order.
+    test "converges for eigenvalues of opposite sign" do
+      t = Nx.tensor([[0, 1], [1, 0]], type: {:f, 32})
+      {eigenvals, eigenvecs} = Nx.LinAlg.eigh(t)
+
+      assert_close(Nx.sort(eigenvals), Nx.tensor([-1.0, 1.0]))
+      assert_close(eigenvecs |> Nx.multiply(eigenvals) |> Nx.dot(Nx.transpose(eigenvecs)), t)
+    end
+
+    test "raises when max_iter is exhausted" do
+      t = Nx.tensor([[4, 1, 2, 0.5], [1, 3, 0, 1], [2, 0, 2, 1], [0.5, 1, 1, 1]])
+
+      assert_raise RuntimeError, "eigenvalues did not converge in 1 iterations", fn ->
+        Nx.LinAlg.eigh(t, max_iter: 1)
+      end
+    end
+
+    test "works on batches of matrices" do
+      t = Nx.tensor([[[2, 0], [0, -3]], [[1, 2], [2, 1]]], type: {:f, 32})
+      {eigenvals, _eigenvecs} = Nx.LinAlg.eigh(t)
+
+      for i <- 0..1 do
+        matrix = Nx.backend_copy(t[i], Nx.BinaryBackend)
+        assert_close(eigenvals[i], matrix |> Nx.LinAlg.eigh() |> elem(0))
+      end
+    end
+  end
+
+  describe "invert" do
+    test "matches Nx.BinaryBackend" do
+      t = Nx.tensor([[1, 2, 1, 1], [0, 1, 0, 1], [0, 0, 1, 1], [0, 0, 0, 1]], type: {:f, 64})
+
+      assert_close_to_binary_backend(t, &Nx.LinAlg.invert/1)
+    end
+
+    test "raises on singular matrices" do
+      for t <- [Nx.tensor([[0, 0], [0, 0]]), Nx.tensor([[1, 2, 3], [4, 5, 6], [7, 8, 9]])] do
+        assert_raise ArgumentError, "can't solve for singular matrix", fn ->
+          Nx.LinAlg.invert(t)
+        end
+      end
+    end
+
+    test "raises on singular matrices in a batch" do
+      t = Nx.tensor([[[1, 0], [0, 1]], [[1, 2], [2, 4]]])
+
+      assert_raise ArgumentError, "can't solve for singular matrix", fn ->
+        Nx.LinAlg.invert(t)
+      end
+    end
+
+    test "of complex matrices" do
+      t = Nx.tensor([[Complex.new(1, 1), 2], [3, Complex.new(4, -1)]])
+
+      assert_close(Nx.dot(t, Nx.LinAlg.invert(t)), Nx.eye(2))
+    end
+  end
+
+  describe "matrix_power" do
+    test "of negative powers" do
+      t = Nx.tensor([[1, 2], [3, 4]])
+
+      assert_close(Nx.LinAlg.matrix_power(t, -1), Nx.tensor([[-2.0, 1.0], [1.5, -0.5]]))
+      assert_close(Nx.LinAlg.matrix_power(t, -2), Nx.tensor([[5.5, -2.5], [-3.75, 1.75]]))
+    end
+
+    test "matches Nx.BinaryBackend" do
+      t = Nx.tensor([[1, 2], [3, 4]], type: {:f, 64})
+
+      for power <- [0, 1, 6, -3] do
+        assert_close_to_binary_backend(t, &Nx.LinAlg.matrix_power(&1, power))
+      end
+    end
+  end
+
+  describe "determinant" do
+    for type <- [{:s, 64}, {:f, 32}, {:f, 64}] do
+      test "of #{Nx.Type.to_string(type)} matrices" do
+        t = Nx.tensor([[6, 1, 1], [4, -2, 5], [2, 8, 7]], type: unquote(type))
+
+        assert_close_to_binary_backend(t, &Nx.LinAlg.determinant/1)
+      end
+    end
+
+    test "of batches of matrices" do
+      t = Nx.tensor([[[1, 2], [3, 4]], [[0, 1], [1, 0]], [[2, 0], [0, 2]]], type: {:f, 32})
+
+      assert_close(Nx.LinAlg.determinant(t), Nx.tensor([-2.0, -1.0, 4.0]))
+    end
+
+    test "of f32 integer matrices is exact" do
+      t = Nx.tensor([[1, 2], [3, 4]], type: {:f, 32})
+
+      assert Nx.to_number(Nx.LinAlg.determinant(t)) == -2.0
+    end
+
+    test "of complex matrices" do
+      t = Nx.tensor([[1, 0, 0], [0, Complex.new(0, 2), 0], [0, 0, 3]])
+
+      assert_close(Nx.LinAlg.determinant(t), Nx.tensor(Complex.new(0, 6)))
+    end
+  end
+
+  defp assert_close_to_binary_backend(inputs, fun, opts \\ []) do
+    binary_inputs = transfer(inputs, &Nx.backend_copy(&1, Nx.BinaryBackend))
+    assert_close(fun.(inputs), fun.(binary_inputs), opts)
+  end
+
+  # assert_all_close is a no-op for now, so results are compared
+  # on Nx.BinaryBackend.
+  defp assert_close(left, right, opts \\ [])
+
+  defp assert_close(left, right, opts) when is_tuple(left) do
+    left
+    |> Tuple.to_list()
+    |> Enum.zip(Tuple.to_list(right))
+    |> Enum.each(fn {left, right} -> assert_close(left, right, opts) end)
+  end
+
+  defp assert_close(left, right, opts) do
+    left = Nx.backend_copy(left, Nx.BinaryBackend)
+    right = Nx.backend_copy(right, Nx.BinaryBackend)
+
+    unless Nx.to_number(Nx.all_close(left, right, Keyword.merge([atol: 1.0e-4], opts))) == 1 do
+      flunk("""
+      Tensor assertion failed.
+      left: #{inspect(left)}
+      right: #{inspect(right)}
+      """)
+    end
+  end
+
+  defp transfer(inputs, fun) when is_tuple(inputs) do
+    inputs
+    |> Tuple.to_list()
+    |> Enum.map(fun)
+    |> List.to_tuple()
+  end
+
+  defp transfer(input, fun), do: fun.(input)
+end
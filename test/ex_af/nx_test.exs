@@ -40,7 +40,9 @@ defmodule ExAF.NxTest do
     :real
   ]
 
-  @binary_ops Helpers.binary_ops() -- [:left_shift, :right_shift]
+  @integer_binary_ops [:left_shift, :right_shift, :bitwise_and, :bitwise_or, :bitwise_xor]
+
+  @binary_ops Helpers.binary_ops() -- @integer_binary_ops
 
   @rounding_unary_ops [
     :expm1,
@@ -84,7 +86,25 @@ defmodule ExAF.NxTest do
 
       Nx.backend_deallocate(t)
 
-      assert Nx.backend_deallocate(t) == :ok
+      assert Nx.backend_deallocate(t) == :already_deallocated
+    end
+
+    test "returns :already_deallocated from NIFs on deallocated tensors" do
+      t = Nx.tensor([1, 2, 3])
+
+      Nx.backend_deallocate(t)
+
+      assert ExAF.Native.to_binary(t.data, 3) == {:error, :already_deallocated}
+      assert ExAF.Native.exp(t.data) == {:error, :already_deallocated}
+    end
+
+    test "raises on deallocated tensors" do
+      t = Nx.tensor([1, 2, 3])
+
+      Nx.backend_deallocate(t)
+
+      assert_raise ArgumentError, ~r/deallocated/, fn -> Nx.exp(t) end
+      assert_raise ArgumentError, ~r/deallocated/, fn -> Nx.to_binary(t) end
     end
   end
 
@@ -99,6 +119,64 @@ defmodule ExAF.NxTest do
     assert backend == Nx.BinaryBackend
   end
 
+  describe "errors" do
+    test "are returned for unsupported types" do
+      t = Nx.tensor([1, 2, 3])
+
+      assert ExAF.Native.as_type(t.data, "s8") == {:error, "unsupported dtype: s8"}
+    end
+
+    test "are returned for sorting complex numbers" do
+      t = Nx.tensor([Complex.new(1, 2), Complex.new(0, 1)])
+
+      assert ExAF.Native.sort(t.data, 0, false) ==
+               {:error, "unsupported dtype: c64, complex numbers can't be sorted"}
+    end
+
+    test "are returned for bitwise operations on floats" do
+      t = Nx.tensor([1.0, 2.0, 3.0])
+
+      assert ExAF.Native.bitwise_and(t.data, t.data) ==
+               {:error, "unsupported dtype: f32, bitwise operations are only defined on integers"}
+
+      assert ExAF.Native.population_count(t.data) ==
+               {:error, "unsupported dtype: f32, bitwise operations are only defined on integers"}
+    end
+
+    test "are returned for arrays with different dtypes" do
+      t = Nx.tensor([1, 2, 3])
+      slice = Nx.tensor([1.0])
+
+      assert ExAF.Native.put_slice(t.data, [0], slice.data) ==
+               {:error, "unsupported dtype: s64 and f32, arrays must have the same dtype"}
+    end
+
+    test "are returned for binaries that don't match the shape" do
+      assert {:error, "invalid shape: " <> _} =
+               ExAF.Native.from_binary(<<1, 2>>, [4], "f32")
+    end
+
+    test "are returned for ArrayFire errors with the op name, shapes and dtypes" do
+      t = Nx.tensor([1, 2, 3])
+
+      assert {:error, "ArrayFire error in atan2: " <> message} =
+               ExAF.Native.atan2(t.data, t.data)
+
+      assert message =~ "[3]"
+      assert message =~ "s64"
+    end
+
+    test "are raised by the backend" do
+      t = Nx.tensor([1, 2, 3])
+
+      assert_raise RuntimeError, ~r/ArrayFire error in atan2/, fn ->
+        t.data
+        |> ExAF.Native.atan2(t.data)
+        |> ExAF.Helpers.to_nx(t)
+      end
+    end
+  end
+
   # Creation
 
   describe "tensor" do
@@ -174,6 +252,99 @@ defmodule ExAF.NxTest do
     assert_equal(t1, t2)
   end
 
+  describe "layout" do
+    for shape <- [{2, 3}, {2, 3, 4}, {2, 3, 4, 5}] do
+      rank = tuple_size(shape)
+
+      test "round trips a rank #{rank} tensor" do
+        t = Nx.iota(unquote(Macro.escape(shape)), backend: Nx.BinaryBackend)
+
+        assert_equal(Nx.backend_copy(t, ExAF.Backend), t)
+      end
+
+      test "iota of rank #{rank} without an axis" do
+        test_layout(&Nx.iota(unquote(Macro.escape(shape)), backend: &1))
+      end
+
+      for axis <- 0..(rank - 1) do
+        test "iota of rank #{rank} along axis #{axis}" do
+          test_layout(&Nx.iota(unquote(Macro.escape(shape)), axis: unquote(axis), backend: &1))
+        end
+      end
+
+      test "eye of rank #{rank}" do
+        test_layout(&Nx.eye(unquote(Macro.escape(shape)), backend: &1))
+      end
+
+      test "reshape of rank #{rank}" do
+        test_layout(fn backend ->
+          unquote(Macro.escape(shape))
+          |> Nx.iota(backend: backend)
+          |> Nx.reshape({:auto, 2})
+        end)
+      end
+
+      test "broadcast to rank #{rank}" do
+        test_layout(fn backend ->
+          [1, 2]
+          |> Nx.tensor(backend: backend)
+          |> Nx.broadcast(unquote(Macro.escape(shape)), axes: [0])
+        end)
+      end
+    end
+  end
+
+  describe "higher ranks" do
+    for shape <- [{2, 3, 2, 2, 3}, {2, 1, 3, 2, 2, 3}] do
+      rank = tuple_size(shape)
+
+      test "round trips a rank #{rank} tensor" do
+        t = Nx.iota(unquote(Macro.escape(shape)), backend: Nx.BinaryBackend)
+
+        assert_equal(Nx.backend_copy(t, ExAF.Backend), t)
+      end
+
+      for axis <- 0..(rank - 1) do
+        test "iota of rank #{rank} along axis #{axis}" do
+          test_layout(&Nx.iota(unquote(Macro.escape(shape)), axis: unquote(axis), backend: &1))
+        end
+      end
+
+      test "eye of rank #{rank}" do
+        test_layout(&Nx.eye(unquote(Macro.escape(shape)), backend: &1))
+      end
+
+      test "elementwise ops of rank #{rank}" do
+        test_layout(fn backend ->
+          t = Nx.iota(unquote(Macro.escape(shape)), backend: backend)
+
+          t
+          |> Nx.multiply(t)
+          |> Nx.add(1)
+          |> Nx.exp()
+        end)
+      end
+
+      test "reshape from and to rank #{rank}" do
+        test_layout(fn backend ->
+          unquote(Macro.escape(shape))
+          |> Nx.iota(backend: backend)
+          |> Nx.reshape({:auto, 6})
+          |> Nx.add(1)
+          |> Nx.reshape(unquote(Macro.escape(shape)))
+        end)
+      end
+
+      test "broadcast to rank #{rank}" do
+        test_layout(fn backend ->
+          [[1], [2]]
+          |> Nx.tensor(backend: backend)
+          |> Nx.broadcast(unquote(Macro.escape(shape)))
+        end)
+      end
+    end
+  end
+
   # Elementwise
 
   describe "manual rounding error tests" do
@@ -213,6 +384,21 @@ defmodule ExAF.NxTest do
     end
   end
 
+  describe "bitwise unary ops" do
+    for op <- [:bitwise_not, :population_count, :count_leading_zeros], type <- @integers do
+      test "#{op}(#{Nx.Type.to_string(type)})" do
+        test_unary_op(unquote(op), [[0, 1], [37, 127]], unquote(type))
+      end
+    end
+
+    for op <- [:bitwise_not, :population_count, :count_leading_zeros],
+        type <- [{:s, 16}, {:s, 32}, {:s, 64}] do
+      test "#{op}(#{Nx.Type.to_string(type)}) with negative numbers" do
+        test_unary_op(unquote(op), [[-1, -2], [-37, -128]], unquote(type))
+      end
+    end
+  end
+
   describe "binary ops" do
     for op <- @binary_ops -- [:divide],
         type_left <- @real_types,
@@ -222,7 +408,7 @@ defmodule ExAF.NxTest do
       end
     end
 
-    for op <- [:right_shift, :left_shift],
+    for op <- @integer_binary_ops,
         type_left <- @integers,
         type_right <- @integers do
       test "#{op}(#{Nx.Type.to_string(type_left)}, #{Nx.Type.to_string(type_right)})" do
@@ -231,6 +417,1236 @@ defmodule ExAF.NxTest do
     end
   end
 
+  describe "broadcasted binary ops" do
+    for op <- @binary_ops -- [:divide] do
+      test "#{op} with a scalar" do
+        test_binary_op(unquote(op), [[5, 6], [7, 8]], 2, {:s, 64}, {:s, 64})
+      end
+
+      test "#{op} with a lower rank" do
+        test_binary_op(unquote(op), [[5, 6], [7, 8]], [1, 2], {:s, 64}, {:s, 64})
+      end
+
+      test "#{op} with size 1 axes" do
+        test_binary_op(unquote(op), [[5], [6], [7]], [[1, 2]], {:f, 32}, {:s, 64})
+      end
+    end
+
+    for op <- [:bitwise_and, :bitwise_or, :bitwise_xor] do
+      test "#{op} with a scalar" do
+        test_binary_op(unquote(op), [[5, 6], [7, 8]], 3, {:s, 64}, {:u, 8})
+      end
+
+      test "#{op} with size 1 axes" do
+        test_binary_op(unquote(op), [[5], [-6], [7]], [[1, -2]], {:s, 32}, {:s, 16})
+      end
+    end
+  end
+
+  # Aggregates
+
+  describe "aggregates" do
+    for op <- Helpers.reduction_ops() do
+      test "#{op} over the whole tensor" do
+        test_layout(&apply(Nx, unquote(op), [Nx.iota({2, 3, 4}, backend: &1)]))
+      end
+
+      test "#{op} over the whole tensor keeping axes" do
+        test_layout(&apply(Nx, unquote(op), [Nx.iota({2, 3, 4}, backend: &1), [keep_axes: true]]))
+      end
+
+      for axes <- [[0], [1], [2], [0, 2], [2, 0]] do
+        test "#{op} over axes #{inspect(axes)}" do
+          test_layout(
+            &apply(Nx, unquote(op), [Nx.iota({2, 3, 4}, backend: &1), [axes: unquote(axes)]])
+          )
+        end
+
+        test "#{op} over axes #{inspect(axes)} keeping axes" do
+          test_layout(
+            &apply(Nx, unquote(op), [
+              Nx.iota({2, 3, 4}, backend: &1),
+              [axes: unquote(axes), keep_axes: true]
+            ])
+          )
+        end
+      end
+
+      test "#{op} over folded axes" do
+        test_layout(
+          &apply(Nx, unquote(op), [Nx.iota({2, 3, 2, 2, 3}, backend: &1), [axes: [0, 1, 3]]])
+        )
+      end
+    end
+
+    for type <- @real_types do
+      test "sum(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.sum(Nx.tensor([[100, 101], [102, 103]], type: unquote(type), backend: &1))
+        )
+      end
+
+      test "product(#{Nx.Type.to_string(type)})" do
+        test_layout(&Nx.product(Nx.tensor([[1, 2], [3, 4]], type: unquote(type), backend: &1)))
+      end
+    end
+
+    for op <- [:argmax, :argmin], tie_break <- [:low, :high] do
+      test "#{op} with #{tie_break} tie break" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([[1, 3, 3, 1], [2, 2, 0, 0], [4, 1, 4, 1]], backend: &1),
+            [tie_break: unquote(tie_break)]
+          ])
+        )
+      end
+
+      for axis <- [0, 1] do
+        test "#{op} over axis #{axis} with #{tie_break} tie break" do
+          test_layout(
+            &apply(Nx, unquote(op), [
+              Nx.tensor([[1, 3, 3, 1], [2, 2, 0, 0], [4, 1, 4, 1]], backend: &1),
+              [axis: unquote(axis), tie_break: unquote(tie_break)]
+            ])
+          )
+        end
+      end
+
+      test "#{op} over axis 1 with #{tie_break} tie break keeping axis" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([[1, 3, 3, 1], [2, 2, 0, 0], [4, 1, 4, 1]], backend: &1),
+            [axis: 1, tie_break: unquote(tie_break), keep_axis: true]
+          ])
+        )
+      end
+
+      test "#{op} over a folded axis with #{tie_break} tie break" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.remainder(Nx.iota({2, 3, 2, 2, 3}, backend: &1), Nx.tensor(5, backend: &1)),
+            [axis: 1, tie_break: unquote(tie_break)]
+          ])
+        )
+      end
+    end
+  end
+
+  describe "cumulative aggregates" do
+    for op <- [:cumulative_sum, :cumulative_product, :cumulative_max, :cumulative_min] do
+      test "#{op}" do
+        test_layout(&apply(Nx, unquote(op), [Nx.tensor([3, 1, 4, 1, 5, 9, 2], backend: &1)]))
+      end
+
+      for axis <- [0, 1], reverse <- [false, true] do
+        test "#{op} over axis #{axis} with reverse #{reverse}" do
+          test_layout(
+            &apply(Nx, unquote(op), [
+              Nx.tensor([[3, 1, 4, 1], [5, 9, 2, 6], [5, 3, 5, 8]], backend: &1),
+              [axis: unquote(axis), reverse: unquote(reverse)]
+            ])
+          )
+        end
+      end
+
+      test "#{op} over a folded axis" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.remainder(Nx.iota({2, 3, 2, 2, 3}, backend: &1), Nx.tensor(7, backend: &1)),
+            [axis: 2, reverse: true]
+          ])
+        )
+      end
+    end
+
+    for type <- @real_types do
+      test "cumulative_sum(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.cumulative_sum(Nx.tensor([100, 101, 102, 103], type: unquote(type), backend: &1))
+        )
+      end
+
+      test "cumulative_max(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.cumulative_max(Nx.tensor([2, 1, 4, 3], type: unquote(type), backend: &1),
+            reverse: true
+          )
+        )
+      end
+    end
+  end
+
+  describe "window aggregates" do
+    for op <- [:window_sum, :window_product, :window_max, :window_min] do
+      test "#{op} with valid padding" do
+        test_layout(&apply(Nx, unquote(op), [Nx.iota({4, 6}, backend: &1), {2, 3}]))
+      end
+
+      test "#{op} with same padding" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({1, 2, 4, 5}, type: {:f, 32}, backend: &1),
+            {1, 1, 3, 3},
+            [padding: :same]
+          ])
+        )
+      end
+
+      test "#{op} with explicit padding and strides" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({3, 5}, backend: &1),
+            {2, 2},
+            [padding: [{1, 2}, {0, 1}], strides: [2, 1]]
+          ])
+        )
+      end
+
+      test "#{op} with window dilations" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({4, 6}, type: {:f, 32}, backend: &1),
+            {2, 2},
+            [window_dilations: [2, 3]]
+          ])
+        )
+      end
+
+      test "#{op} over leading axes" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({3, 2, 4}, backend: &1),
+            {2, 1, 2},
+            [strides: [1, 2, 1], padding: :same]
+          ])
+        )
+      end
+
+      test "#{op} with higher ranks" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+            {1, 2, 1, 2, 2},
+            [padding: :same]
+          ])
+        )
+      end
+    end
+
+    for type <- @real_types do
+      test "window_sum(#{Nx.Type.to_string(type)})" do
+        test_layout(&Nx.window_sum(Nx.iota({2, 4}, type: unquote(type), backend: &1), {2, 2}))
+      end
+
+      test "window_max(#{Nx.Type.to_string(type)}) with padding" do
+        test_layout(
+          &Nx.window_max(Nx.iota({2, 4}, type: unquote(type), backend: &1), {2, 2},
+            padding: :same
+          )
+        )
+      end
+    end
+  end
+
+  describe "window scatters" do
+    for op <- [:window_scatter_max, :window_scatter_min] do
+      test "#{op} with non overlapping windows" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([[7, 2, 5, 3], [3, 9, 1, 8], [4, 6, 2, 0], [5, 1, 3, 7]], backend: &1),
+            Nx.tensor([[1, 2], [3, 4]], backend: &1),
+            Nx.tensor(0, backend: &1),
+            {2, 2},
+            [strides: [2, 2]]
+          ])
+        )
+      end
+
+      test "#{op} with overlapping windows" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([[7, 2, 5, 3], [3, 9, 1, 8], [4, 6, 2, 0]], backend: &1),
+            Nx.tensor([[1, 2, 3], [4, 5, 6]], backend: &1),
+            Nx.tensor(10, backend: &1),
+            {2, 2}
+          ])
+        )
+      end
+
+      test "#{op} with padding" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([[7.0, 2.0, 5.0], [3.0, 9.0, 1.0]], backend: &1),
+            Nx.tensor([[1.0, 2.0], [3.0, 4.0]], backend: &1),
+            Nx.tensor(0.5, backend: &1),
+            {2, 2},
+            [padding: [{1, 1}, {0, 1}], strides: [2, 2]]
+          ])
+        )
+      end
+
+      test "#{op} with higher ranks" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({2, 3, 2, 2, 2}, backend: &1),
+            Nx.iota({2, 3, 1, 1, 1}, backend: &1),
+            Nx.tensor(0, backend: &1),
+            {1, 1, 2, 2, 2},
+            [strides: [1, 1, 2, 2, 2]]
+          ])
+        )
+      end
+    end
+  end
+
+  # Sorting
+
+  describe "sort and argsort" do
+    for op <- [:sort, :argsort], direction <- [:asc, :desc] do
+      test "#{op} #{direction}" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([3, 1, 4, 1, 5, 9, 2, 6], backend: &1),
+            [direction: unquote(direction)]
+          ])
+        )
+      end
+
+      for axis <- [0, 1, 2] do
+        test "#{op} #{direction} over axis #{axis}" do
+          test_layout(
+            &apply(Nx, unquote(op), [
+              Nx.remainder(Nx.iota({2, 3, 4}, backend: &1), Nx.tensor(3, backend: &1)),
+              [axis: unquote(axis), direction: unquote(direction)]
+            ])
+          )
+        end
+      end
+
+      test "#{op} #{direction} over a folded axis" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.remainder(Nx.iota({2, 3, 2, 2, 3}, backend: &1), Nx.tensor(4, backend: &1)),
+            [axis: 3, direction: unquote(direction)]
+          ])
+        )
+      end
+
+      test "#{op} #{direction} with NaNs and infinities" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([1.0, :nan, :infinity, -2.0, :neg_infinity, :nan, 1.0], backend: &1),
+            [direction: unquote(direction)]
+          ])
+        )
+      end
+    end
+
+    for type <- @real_types do
+      test "sort(#{Nx.Type.to_string(type)})" do
+        test_layout(&Nx.sort(Nx.tensor([[4, 2, 3], [1, 5, 0]], type: unquote(type), backend: &1)))
+      end
+    end
+
+    test "argsort keeps ties in their original order" do
+      t = Nx.tensor([2, 1, 2, 1, 2, 1])
+
+      assert Nx.to_flat_list(Nx.argsort(t)) == [1, 3, 5, 0, 2, 4]
+      assert Nx.to_flat_list(Nx.argsort(t, direction: :desc)) == [0, 2, 4, 1, 3, 5]
+    end
+  end
+
+  describe "top_k" do
+    for type <- @real_types, k <- [1, 3] do
+      test "top_k(#{Nx.Type.to_string(type)}) with k #{k}" do
+        test_layout(fn backend ->
+          [[3, 1, 4, 7], [5, 9, 2, 6], [0, 8, 10, 11]]
+          |> Nx.tensor(type: unquote(type), backend: backend)
+          |> Nx.top_k(k: unquote(k))
+          |> Tuple.to_list()
+          |> Nx.stack()
+        end)
+      end
+    end
+
+    test "over a folded axis" do
+      test_layout(fn backend ->
+        {2, 3, 2, 2, 5}
+        |> Nx.iota(type: {:f, 32}, backend: backend)
+        |> Nx.top_k(k: 2)
+        |> Tuple.to_list()
+        |> Nx.stack()
+      end)
+    end
+
+    test "with k larger than ArrayFire's topk supports" do
+      test_layout(fn backend ->
+        {2, 300}
+        |> Nx.iota(type: {:f, 32}, backend: backend)
+        |> Nx.top_k(k: 260)
+        |> Tuple.to_list()
+        |> Nx.stack()
+      end)
+    end
+
+    test "keeps ties in their original order on the fallback" do
+      t = Nx.tensor([2, 1, 2, 1, 2], type: {:s, 64})
+      {values, indices} = Nx.top_k(t, k: 4)
+
+      assert Nx.to_flat_list(values) == [2, 2, 2, 1]
+      assert Nx.to_flat_list(indices) == [0, 2, 4, 1]
+    end
+
+    for type <- [{:s, 32}, {:u, 32}, {:f, 32}, {:f, 64}], k <- [1, 2, 3] do
+      test "keeps ties in their original order(#{Nx.Type.to_string(type)}) with k #{k}" do
+        test_layout(fn backend ->
+          [[2, 1, 2, 0, 2], [0, 5, 5, 5, 1], [4, 3, 3, 4, 3]]
+          |> Nx.tensor(type: unquote(type), backend: backend)
+          |> Nx.top_k(k: unquote(k))
+          |> Tuple.to_list()
+          |> Nx.stack()
+        end)
+      end
+    end
+
+    for type <- [{:f, 32}, {:f, 64}], k <- [1, 3] do
+      test "places NaNs first(#{Nx.Type.to_string(type)}) with k #{k}" do
+        test_layout(fn backend ->
+          [[1.0, :nan, 3.0, :nan], [:nan, 2.0, :infinity, 0.0]]
+          |> Nx.tensor(type: unquote(type), backend: backend)
+          |> Nx.top_k(k: unquote(k))
+          |> Tuple.to_list()
+          |> Nx.stack()
+        end)
+      end
+    end
+
+    test "orders ties by position" do
+      t = Nx.tensor([[2, 1, 2, 0, 2], [0, 5, 5, 5, 1]], type: {:f, 32})
+      {values, indices} = Nx.top_k(t, k: 3)
+
+      assert Nx.to_flat_list(values) == [2.0, 2.0, 2.0, 5.0, 5.0, 5.0]
+      assert Nx.to_flat_list(indices) == [0, 2, 4, 1, 2, 3]
+    end
+  end
+
+  # Linear Algebra
+
+  describe "dot" do
+    for type <- @supported_types do
+      test "vectors(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.dot(
+            Nx.iota({3}, type: unquote(type), backend: &1),
+            Nx.iota({3}, type: unquote(type), backend: &1)
+          )
+        )
+      end
+
+      test "matrix and vector(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.dot(
+            Nx.iota({2, 3}, type: unquote(type), backend: &1),
+            Nx.iota({3}, type: unquote(type), backend: &1)
+          )
+        )
+      end
+
+      test "matrices(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.dot(
+            Nx.iota({2, 3}, type: unquote(type), backend: &1),
+            Nx.iota({3, 4}, type: unquote(type), backend: &1)
+          )
+        )
+      end
+    end
+
+    for type <- [{:s, 64}, {:u, 64}] do
+      test "large integers exactly(#{Nx.Type.to_string(type)})" do
+        test_layout(
+          &Nx.dot(
+            Nx.tensor([[9_007_199_254_740_993, 3], [1, 2]], type: unquote(type), backend: &1),
+            Nx.tensor([[1, 5], [1, 7]], type: unquote(type), backend: &1)
+          )
+        )
+      end
+    end
+
+    test "wraps integer overflow" do
+      test_layout(
+        &Nx.dot(
+          Nx.tensor([4_611_686_018_427_387_904, 3, 1_000], type: {:s, 64}, backend: &1),
+          Nx.tensor([2, 3_074_457_345_618_258_603, 7], type: {:s, 64}, backend: &1)
+        )
+      )
+    end
+
+    for type <- [{:c, 64}, {:c, 128}] do
+      test "matrices with imaginary parts(#{Nx.Type.to_string(type)})" do
+        left = [[Complex.new(1, 2), Complex.new(0, -1)], [Complex.new(3, 0), Complex.new(2, 1)]]
+        right = [[Complex.new(2, -1), Complex.new(1, 1)], [Complex.new(0, 3), Complex.new(-1, 0)]]
+
+        test_layout(
+          &Nx.dot(
+            Nx.tensor(left, type: unquote(type), backend: &1),
+            Nx.tensor(right, type: unquote(type), backend: &1)
+          )
+        )
+      end
+    end
+
+    test "complex and real types" do
+      test_layout(
+        &Nx.dot(
+          Nx.tensor([Complex.new(1, 2), Complex.new(-1, 3)], backend: &1),
+          Nx.iota({2, 3}, type: {:f, 32}, backend: &1)
+        )
+      )
+    end
+
+    test "mixed types" do
+      test_layout(
+        &Nx.dot(
+          Nx.iota({2, 3}, type: {:s, 32}, backend: &1),
+          Nx.iota({3, 2}, type: {:f, 32}, backend: &1)
+        )
+      )
+    end
+
+    test "outer product" do
+      test_layout(&Nx.dot(Nx.iota({2, 3}, backend: &1), [], Nx.iota({4}, backend: &1), []))
+    end
+
+    for {axes1, axes2} <- [{[0], [0]}, {[0], [1]}, {[1], [0]}, {[1], [1]}] do
+      test "contracting axes #{inspect(axes1)} and #{inspect(axes2)}" do
+        test_layout(
+          &Nx.dot(
+            Nx.iota({3, 3}, backend: &1),
+            unquote(axes1),
+            Nx.tensor([[1, 2, 3], [4, 5, 6], [7, 8, 10]], backend: &1),
+            unquote(axes2)
+          )
+        )
+      end
+    end
+
+    test "contracting several axes" do
+      test_layout(
+        &Nx.dot(Nx.iota({2, 3, 4}, backend: &1), [2, 0], Nx.iota({4, 5, 2}, backend: &1), [0, 2])
+      )
+    end
+
+    test "batched" do
+      test_layout(
+        &Nx.dot(
+          Nx.iota({2, 3, 4}, backend: &1),
+          [2],
+          [0],
+          Nx.iota({2, 4, 5}, backend: &1),
+          [1],
+          [0]
+        )
+      )
+    end
+
+    test "batched over inner axes" do
+      test_layout(
+        &Nx.dot(
+          Nx.iota({3, 2, 4}, backend: &1),
+          [0],
+          [1],
+          Nx.iota({5, 3, 2}, backend: &1),
+          [1],
+          [2]
+        )
+      )
+    end
+
+    test "batched over several axes" do
+      test_layout(
+        &Nx.dot(
+          Nx.iota({2, 2, 3, 4}, backend: &1),
+          [3],
+          [0, 1],
+          Nx.iota({2, 2, 4, 3}, backend: &1),
+          [2],
+          [0, 1]
+        )
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.dot(
+          Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+          [1, 4],
+          [0],
+          Nx.iota({2, 3, 3, 2}, backend: &1),
+          [2, 1],
+          [0]
+        )
+      )
+    end
+  end
+
+  # Indexing
+
+  describe "slice" do
+    test "rank 1" do
+      test_layout(&Nx.slice(Nx.iota({10}, backend: &1), [2], [5]))
+    end
+
+    test "rank 3" do
+      test_layout(&Nx.slice(Nx.iota({4, 5, 6}, backend: &1), [1, 0, 2], [2, 3, 4]))
+    end
+
+    test "with strides" do
+      test_layout(
+        &Nx.slice(Nx.iota({4, 5, 6}, backend: &1), [0, 1, 1], [4, 4, 5], strides: [2, 3, 2])
+      )
+    end
+
+    test "with tensor start indices" do
+      test_layout(
+        &Nx.slice(
+          Nx.iota({4, 5}, backend: &1),
+          [Nx.tensor(1, backend: &1), Nx.tensor(2, backend: &1)],
+          [2, 3]
+        )
+      )
+    end
+
+    test "clamps tensor start indices" do
+      test_layout(
+        &Nx.slice(
+          Nx.iota({4, 5}, backend: &1),
+          [Nx.tensor(-1, backend: &1), Nx.tensor(4, backend: &1)],
+          [2, 3]
+        )
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.slice(
+          Nx.iota({2, 3, 4, 2, 3}, backend: &1),
+          [1, 0, 1, 0, 1],
+          [1, 3, 3, 2, 2],
+          strides: [1, 2, 2, 1, 1]
+        )
+      )
+    end
+
+    test "complex" do
+      test_layout(&Nx.slice(Nx.iota({4, 5}, type: {:c, 64}, backend: &1), [1, 1], [2, 2]))
+    end
+  end
+
+  describe "put_slice" do
+    test "rank 1" do
+      test_layout(
+        &Nx.put_slice(Nx.iota({10}, backend: &1), [2], Nx.tensor([-1, -2, -3], backend: &1))
+      )
+    end
+
+    test "rank 3" do
+      test_layout(
+        &Nx.put_slice(
+          Nx.iota({4, 5, 6}, backend: &1),
+          [1, 2, 3],
+          Nx.broadcast(Nx.tensor(-1, backend: &1), {2, 3, 2})
+        )
+      )
+    end
+
+    test "clamps start indices" do
+      test_layout(
+        &Nx.put_slice(
+          Nx.iota({4, 5}, backend: &1),
+          [Nx.tensor(3, backend: &1), Nx.tensor(-2, backend: &1)],
+          Nx.tensor([[-1, -2], [-3, -4]], backend: &1)
+        )
+      )
+    end
+
+    test "with a different type" do
+      test_layout(
+        &Nx.put_slice(Nx.iota({4, 5}, backend: &1), [1, 1], Nx.tensor([[1.5, 2.5]], backend: &1))
+      )
+    end
+
+    test "with imaginary parts" do
+      test_layout(
+        &Nx.put_slice(
+          Nx.tensor([Complex.new(1, 2), Complex.new(3, -1), Complex.new(0, 4)], backend: &1),
+          [1],
+          Nx.tensor([Complex.new(-2, 0.5)], type: {:c, 128}, backend: &1)
+        )
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.put_slice(
+          Nx.iota({2, 3, 4, 2, 3}, backend: &1),
+          [1, 1, 2, 0, 1],
+          Nx.multiply(Nx.iota({1, 2, 2, 2, 2}, backend: &1), Nx.tensor(-1, backend: &1))
+        )
+      )
+    end
+  end
+
+  describe "pad" do
+    for config <- [
+          [{1, 2, 0}],
+          [{0, 0, 2}],
+          [{-1, 1, 0}],
+          [{-2, -1, 1}],
+          [{1, -3, 2}]
+        ] do
+      test "rank 1 with #{inspect(config)}" do
+        test_layout(
+          &Nx.pad(Nx.iota({5}, backend: &1), Nx.tensor(-1, backend: &1), unquote(config))
+        )
+      end
+    end
+
+    test "rank 3" do
+      test_layout(
+        &Nx.pad(
+          Nx.iota({2, 3, 4}, backend: &1),
+          Nx.tensor(-1, backend: &1),
+          [{1, 0, 1}, {-1, 2, 0}, {0, -2, 2}]
+        )
+      )
+    end
+
+    test "a scalar" do
+      test_layout(&Nx.pad(Nx.tensor(1, backend: &1), Nx.tensor(0, backend: &1), []))
+    end
+
+    test "with a different type" do
+      test_layout(
+        &Nx.pad(Nx.iota({2, 3}, backend: &1), Nx.tensor(0.5, backend: &1), [{1, 1, 0}, {0, 1, 1}])
+      )
+    end
+
+    test "with imaginary parts" do
+      test_layout(
+        &Nx.pad(
+          Nx.tensor([[Complex.new(1, 2)], [Complex.new(3, -1)]], backend: &1),
+          Nx.tensor(Complex.new(0, -1), backend: &1),
+          [{1, 0, 1}, {0, 2, 0}]
+        )
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.pad(
+          Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+          Nx.tensor(-1, backend: &1),
+          [{0, 1, 0}, {-1, 0, 1}, {1, 0, 0}, {0, 0, 0}, {2, -1, 1}]
+        )
+      )
+    end
+  end
+
+  describe "take" do
+    for axis <- [0, 1, 2] do
+      test "over axis #{axis}" do
+        test_layout(
+          &Nx.take(
+            Nx.iota({2, 3, 4}, backend: &1),
+            Nx.tensor([[1, 0], [1, 1]], backend: &1),
+            axis: unquote(axis)
+          )
+        )
+      end
+    end
+
+    test "with a scalar index" do
+      test_layout(&Nx.take(Nx.iota({2, 3}, backend: &1), Nx.tensor(2, backend: &1), axis: 1))
+    end
+
+    test "with unsigned indices" do
+      test_layout(
+        &Nx.take(Nx.iota({3, 2}, backend: &1), Nx.tensor([2, 0, 2], type: {:u, 8}, backend: &1))
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.take(
+          Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+          Nx.tensor([2, 0, 1, 1], backend: &1),
+          axis: 1
+        )
+      )
+    end
+
+    test "clamps out of range indices" do
+      t = Nx.take(Nx.tensor([1, 2, 3]), Nx.tensor([-1, 3, 1]))
+
+      assert_equal(t, Nx.tensor([1, 3, 2]))
+    end
+  end
+
+  describe "take_along_axis" do
+    for axis <- [0, 1, 2] do
+      test "over axis #{axis}" do
+        test_layout(
+          &Nx.take_along_axis(
+            Nx.iota({2, 3, 4}, backend: &1),
+            Nx.remainder(Nx.iota({2, 3, 4}, axis: 0, backend: &1), Nx.tensor(2, backend: &1)),
+            axis: unquote(axis)
+          )
+        )
+      end
+    end
+
+    test "with more indices than the axis" do
+      test_layout(
+        &Nx.take_along_axis(
+          Nx.iota({2, 3}, backend: &1),
+          Nx.tensor([[0, 2, 1, 1, 0], [2, 2, 0, 1, 1]], backend: &1),
+          axis: 1
+        )
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.take_along_axis(
+          Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+          Nx.remainder(Nx.iota({2, 3, 2, 2, 3}, backend: &1), Nx.tensor(3, backend: &1)),
+          axis: 4
+        )
+      )
+    end
+
+    test "clamps out of range indices" do
+      t = Nx.take_along_axis(Nx.tensor([[1, 2], [3, 4]]), Nx.tensor([[-1, 2], [5, 0]]), axis: 1)
+
+      assert_equal(t, Nx.tensor([[1, 2], [4, 3]]))
+    end
+  end
+
+  describe "gather" do
+    test "rank 1" do
+      test_layout(&Nx.gather(Nx.iota({4}, backend: &1), Nx.tensor([[3], [0], [2]], backend: &1)))
+    end
+
+    test "rank 3" do
+      test_layout(
+        &Nx.gather(
+          Nx.iota({2, 3, 4}, backend: &1),
+          Nx.tensor([[[1, 2, 3], [0, 0, 0]], [[1, 0, 2], [0, 2, 1]]], backend: &1)
+        )
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.gather(
+          Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+          Nx.tensor([[1, 2, 0, 1, 2], [0, 1, 1, 0, 0]], backend: &1)
+        )
+      )
+    end
+
+    test "clamps out of range indices" do
+      t = Nx.gather(Nx.tensor([[1, 2], [3, 4]]), Nx.tensor([[-1, 1], [1, 7]]))
+
+      assert_equal(t, Nx.tensor([2, 4]))
+    end
+  end
+
+  describe "indexed_add and indexed_put" do
+    for op <- [:indexed_add, :indexed_put] do
+      test "#{op} rank 1" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({5}, backend: &1),
+            Nx.tensor([[0], [3], [4]], backend: &1),
+            Nx.tensor([10, 20, 30], backend: &1)
+          ])
+        )
+      end
+
+      test "#{op} rank 3" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({2, 3, 4}, backend: &1),
+            Nx.tensor([[1, 2, 3], [0, 0, 0], [0, 1, 2]], backend: &1),
+            Nx.tensor([-1, -2, -3], backend: &1)
+          ])
+        )
+      end
+
+      test "#{op} with a different type" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({2, 2}, type: {:u, 8}, backend: &1),
+            Nx.tensor([[1, 0], [0, 1]], backend: &1),
+            Nx.tensor([1.5, 2.5], backend: &1)
+          ])
+        )
+      end
+
+      test "#{op} with imaginary parts" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.tensor([Complex.new(1, 2), Complex.new(0, -1), Complex.new(3, 0)], backend: &1),
+            Nx.tensor([[2], [0]], backend: &1),
+            Nx.tensor([Complex.new(-1, 0.5), Complex.new(2, 3)], backend: &1)
+          ])
+        )
+      end
+
+      test "#{op} higher ranks" do
+        test_layout(
+          &apply(Nx, unquote(op), [
+            Nx.iota({2, 3, 2, 2, 3}, backend: &1),
+            Nx.tensor([[1, 2, 0, 1, 2], [0, 1, 1, 0, 0]], backend: &1),
+            Nx.tensor([100, 200], backend: &1)
+          ])
+        )
+      end
+    end
+  end
+
+  describe "indexed_add with repeated indices" do
+    test "accumulates every update" do
+      test_layout(
+        &Nx.indexed_add(
+          Nx.iota({2, 3}, backend: &1),
+          Nx.tensor([[0, 1], [1, 2], [0, 1], [0, 1], [1, 2]], backend: &1),
+          Nx.tensor([1, 2, 3, 4, 5], backend: &1)
+        )
+      )
+    end
+
+    for type <- [{:u, 8}, {:s, 32}, {:f, 32}, {:c, 64}] do
+      test "accumulates #{Nx.Type.to_string(type)} updates" do
+        test_layout(
+          &Nx.indexed_add(
+            Nx.broadcast(Nx.tensor(0, type: unquote(type), backend: &1), {4}),
+            Nx.tensor([[3], [3], [0], [3]], backend: &1),
+            Nx.tensor([1, 2, 3, 4], type: unquote(type), backend: &1)
+          )
+        )
+      end
+    end
+
+    test "accumulates updates with imaginary parts" do
+      test_layout(
+        &Nx.indexed_add(
+          Nx.tensor([Complex.new(1, 1), Complex.new(0, 0)], backend: &1),
+          Nx.tensor([[1], [0], [1]], backend: &1),
+          Nx.tensor([Complex.new(1, 2), Complex.new(0, -1), Complex.new(-3, 0.5)], backend: &1)
+        )
+      )
+    end
+  end
+
+  # Convolution
+
+  describe "conv" do
+    test "1 spatial axis" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({2, 3, 6}, type: {:f, 32}, backend: &1),
+          Nx.iota({4, 3, 2}, type: {:f, 32}, backend: &1)
+        )
+      )
+    end
+
+    test "2 spatial axes" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({2, 3, 5, 5}, type: {:f, 32}, backend: &1),
+          Nx.iota({4, 3, 3, 2}, type: {:f, 32}, backend: &1),
+          strides: [2, 1],
+          padding: :same
+        )
+      )
+    end
+
+    test "2 spatial axes with kernel dilations" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({1, 2, 6, 6}, type: {:f, 64}, backend: &1),
+          Nx.iota({3, 2, 2, 2}, type: {:f, 64}, backend: &1),
+          kernel_dilation: [2, 3],
+          padding: [{1, 1}, {2, 2}]
+        )
+      )
+    end
+
+    test "3 spatial axes" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({1, 2, 3, 4, 4}, type: {:f, 32}, backend: &1),
+          Nx.iota({2, 2, 2, 2, 3}, type: {:f, 32}, backend: &1),
+          strides: [1, 2, 1]
+        )
+      )
+    end
+
+    test "with asymmetric padding and input dilations" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({1, 1, 3, 4}, type: {:f, 32}, backend: &1),
+          Nx.iota({2, 1, 2, 2}, type: {:f, 32}, backend: &1),
+          padding: [{2, 0}, {0, 1}],
+          input_dilation: [2, 1]
+        )
+      )
+    end
+
+    test "with feature groups" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({2, 4, 5, 5}, type: {:f, 32}, backend: &1),
+          Nx.iota({6, 2, 2, 2}, type: {:f, 32}, backend: &1),
+          feature_group_size: 2
+        )
+      )
+    end
+
+    test "with batch groups" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({4, 2, 4, 4}, type: {:f, 32}, backend: &1),
+          Nx.iota({6, 2, 2, 2}, type: {:f, 32}, backend: &1),
+          batch_group_size: 2
+        )
+      )
+    end
+
+    test "with permutations" do
+      test_layout(
+        &Nx.conv(
+          Nx.iota({2, 5, 5, 3}, type: {:f, 32}, backend: &1),
+          Nx.iota({2, 2, 3, 4}, type: {:f, 32}, backend: &1),
+          input_permutation: [0, 3, 1, 2],
+          kernel_permutation: [3, 2, 0, 1],
+          output_permutation: [0, 3, 1, 2]
+        )
+      )
+    end
+
+    test "integers" do
+      test_layout(
+        &Nx.conv(Nx.iota({1, 1, 4, 4}, backend: &1), Nx.iota({1, 1, 2, 2}, backend: &1))
+      )
+    end
+  end
+
+  # FFT
+
+  describe "fft and ifft" do
+    for op <- [:fft, :ifft] do
+      test "#{op}" do
+        test_layout_close(&apply(Nx, unquote(op), [Nx.tensor([1, 2, 3, 4], backend: &1)]))
+      end
+
+      for length <- [2, 6] do
+        test "#{op} with length #{length}" do
+          test_layout_close(
+            &apply(Nx, unquote(op), [
+              Nx.iota({2, 4}, type: {:f, 32}, backend: &1),
+              [length: unquote(length)]
+            ])
+          )
+        end
+      end
+
+      test "#{op} over a folded axis" do
+        test_layout_close(
+          &apply(Nx, unquote(op), [Nx.iota({2, 3, 2, 2, 4}, type: {:c, 64}, backend: &1)])
+        )
+      end
+
+      for type <- [{:s, 64}, {:f, 64}, {:c, 128}] do
+        test "#{op}(#{Nx.Type.to_string(type)})" do
+          test_layout_close(
+            &apply(Nx, unquote(op), [Nx.tensor([1, 0, 1, 0], type: unquote(type), backend: &1)])
+          )
+        end
+      end
+
+      for type <- [{:c, 64}, {:c, 128}] do
+        test "#{op} with imaginary parts(#{Nx.Type.to_string(type)})" do
+          t = [[Complex.new(1, 2), Complex.new(-1, 0.5), 3], [Complex.new(0, -1), 2, 0]]
+
+          test_layout_close(
+            &apply(Nx, unquote(op), [Nx.tensor(t, type: unquote(type), backend: &1)])
+          )
+        end
+      end
+    end
+
+    test "ifft of fft" do
+      test_layout_close(fn backend ->
+        [[1.0, -2.0, 3.0, 0.5], [0.0, 1.0, 0.0, 1.0]]
+        |> Nx.tensor(backend: backend)
+        |> Nx.fft()
+        |> Nx.ifft()
+      end)
+    end
+
+    test "ifft of fft with imaginary parts" do
+      t = Nx.tensor([Complex.new(1, 2), Complex.new(-1, 0.5), 3, Complex.new(0, -1)])
+      round_trip = t |> Nx.fft() |> Nx.ifft() |> Nx.backend_copy(Nx.BinaryBackend)
+
+      assert Nx.to_number(Nx.all_close(round_trip, Nx.backend_copy(t, Nx.BinaryBackend))) == 1
+    end
+  end
+
+  # Shape
+
+  describe "broadcast" do
+    test "a scalar" do
+      t = Nx.broadcast(Nx.tensor(1), {2, 3})
+
+      assert_equal(t, Nx.tensor([[1, 1, 1], [1, 1, 1]]))
+    end
+
+    test "a lower rank" do
+      t = Nx.broadcast(Nx.tensor([1, 2, 3]), {2, 3})
+
+      assert_equal(t, Nx.tensor([[1, 2, 3], [1, 2, 3]]))
+    end
+
+    test "with axes" do
+      t = Nx.broadcast(Nx.tensor([1, 2]), {2, 3}, axes: [0])
+
+      assert_equal(t, Nx.tensor([[1, 1, 1], [2, 2, 2]]))
+    end
+
+    test "size 1 axes" do
+      t = Nx.broadcast(Nx.tensor([[[1], [2]]]), {3, 2, 2})
+      binary_t = Nx.broadcast(Nx.tensor([[[1], [2]]], backend: Nx.BinaryBackend), {3, 2, 2})
+
+      assert_equal(t, binary_t)
+    end
+  end
+
+  describe "transpose" do
+    for shape <- [{2, 3}, {2, 3, 4}, {2, 3, 4, 5}, {2, 3, 2, 2, 3}] do
+      test "reverses the axes of #{inspect(shape)}" do
+        test_layout(&Nx.transpose(Nx.iota(unquote(Macro.escape(shape)), backend: &1)))
+      end
+    end
+
+    for axes <- [[0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1]] do
+      test "with axes #{inspect(axes)}" do
+        test_layout(&Nx.transpose(Nx.iota({2, 3, 4}, backend: &1), axes: unquote(axes)))
+      end
+    end
+
+    test "moving size one axes" do
+      test_layout(&Nx.transpose(Nx.iota({1, 3, 1, 4}, backend: &1), axes: [2, 1, 0, 3]))
+    end
+
+    test "higher ranks with adjacent axes" do
+      test_layout(&Nx.transpose(Nx.iota({2, 3, 2, 2, 3}, backend: &1), axes: [3, 4, 0, 1, 2]))
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.transpose(Nx.iota({2, 3, 2, 2, 3, 2}, backend: &1), axes: [5, 3, 1, 4, 2, 0])
+      )
+    end
+
+    test "complex" do
+      test_layout(&Nx.transpose(Nx.iota({2, 3}, type: {:c, 64}, backend: &1)))
+    end
+  end
+
+  describe "concatenate" do
+    for axis <- [0, 1, 2] do
+      test "over axis #{axis}" do
+        test_layout(
+          &Nx.concatenate(
+            [
+              Nx.iota({2, 3, 4}, backend: &1),
+              Nx.iota({2, 3, 4}, backend: &1),
+              Nx.iota({2, 3, 4}, backend: &1)
+            ],
+            axis: unquote(axis)
+          )
+        )
+      end
+    end
+
+    test "a single tensor" do
+      test_layout(&Nx.concatenate([Nx.iota({2, 3}, backend: &1)]))
+    end
+
+    test "more than ten tensors" do
+      test_layout(&Nx.concatenate(for(i <- 1..23, do: Nx.iota({i, 2}, backend: &1))))
+    end
+
+    test "mixed types" do
+      test_layout(
+        &Nx.concatenate([
+          Nx.iota({2}, type: {:u, 8}, backend: &1),
+          Nx.iota({3}, type: {:s, 32}, backend: &1),
+          Nx.iota({1}, type: {:f, 32}, backend: &1)
+        ])
+      )
+    end
+
+    test "complex" do
+      test_layout(
+        &Nx.concatenate([
+          Nx.iota({2}, type: {:c, 64}, backend: &1),
+          Nx.iota({3}, type: {:f, 32}, backend: &1)
+        ])
+      )
+    end
+
+    test "mixed complex types with imaginary parts" do
+      test_layout(
+        &Nx.concatenate([
+          Nx.tensor([Complex.new(1, 2), Complex.new(-0.5, 3)], type: {:c, 64}, backend: &1),
+          Nx.tensor([Complex.new(0, -1)], type: {:c, 128}, backend: &1),
+          Nx.tensor([Complex.new(4, 1.5)], type: {:c, 64}, backend: &1)
+        ])
+      )
+    end
+
+    test "higher ranks" do
+      test_layout(
+        &Nx.concatenate(
+          [Nx.iota({2, 3, 2, 2, 3}, backend: &1), Nx.iota({2, 1, 2, 2, 3}, backend: &1)],
+          axis: 1
+        )
+      )
+    end
+  end
+
+  describe "stack" do
+    for axis <- [0, 1, 2] do
+      test "over axis #{axis}" do
+        test_layout(
+          &Nx.stack(
+            [Nx.iota({2, 3}, backend: &1), Nx.iota({2, 3}, type: {:f, 32}, backend: &1)],
+            axis: unquote(axis)
+          )
+        )
+      end
+    end
+
+    test "scalars" do
+      test_layout(&Nx.stack([Nx.tensor(1, backend: &1), Nx.tensor(2, backend: &1)]))
+    end
+  end
+
   # Type
 
   describe "as_type" do
@@ -248,10 +1664,33 @@ defmodule ExAF.NxTest do
         assert_equal(t1, t2)
       end
     end
+
+    for type1 <- [{:c, 64}, {:c, 128}], type2 <- @supported_types do
+      test "#{Nx.Type.to_string(type2)} from #{Nx.Type.to_string(type1)} with imaginary parts" do
+        test_layout(fn backend ->
+          [Complex.new(1, 2), Complex.new(3, -0.5)]
+          |> Nx.tensor(type: unquote(type1), backend: backend)
+          |> Nx.as_type(unquote(type2))
+        end)
+      end
+    end
   end
 
   # Helper functions
 
+  defp test_layout(fun) do
+    assert_equal(fun.(ExAF.Backend), fun.(Nx.BinaryBackend))
+  end
+
+  # Compares results that may differ by rounding on
+  # Nx.BinaryBackend, since assert_all_close is a no-op.
+  defp test_layout_close(fun) do
+    left = Nx.backend_copy(fun.(ExAF.Backend), Nx.BinaryBackend)
+    right = fun.(Nx.BinaryBackend)
+
+    assert Nx.to_number(Nx.all_close(left, right, atol: 1.0e-4)) == 1
+  end
+
   defp apply_unary_op(op, data, type) do
     t = Nx.tensor(data, type: type)
     r = Kernel.apply(Nx, op, [t])
@@ -0,0 +1,88 @@
+defmodule ExAF.RandomTest do
+  use ExAF.Case, async: true
+
+  alias ExAF.Random
+
+  describe "engine" do
+    for type <- [:philox, :threefry, :mersenne] do
+      test "#{type} engines of the same seed generate the same numbers" do
+        left = Random.engine(type: unquote(type), seed: 7)
+        right = Random.engine(type: unquote(type), seed: 7)
+
+        assert_equal(Random.uniform(left, {4, 5}), Random.uniform(right, {4, 5}))
+        assert_equal(Random.normal(left, {4, 5}), Random.normal(right, {4, 5}))
+      end
+    end
+
+    test "advances its state" do
+      engine = Random.engine(seed: 7)
+
+      first = Random.uniform(engine, {8})
+      second = Random.uniform(engine, {8})
+
+      refute Nx.to_binary(first) == Nx.to_binary(second)
+    end
+
+    test "is reset by reseeding" do
+      engine = Random.engine(seed: 7)
+      first = Random.uniform(engine, {8})
+
+      assert Random.seed(engine) == 7
+      assert Random.set_seed(engine, 7) == :ok
+      assert_equal(Random.uniform(engine, {8}), first)
+
+      Random.set_seed(engine, 11)
+      assert Random.seed(engine) == 11
+    end
+
+    test "raises on unknown types" do
+      assert_raise ArgumentError, ~r/expected :type to be one of/, fn ->
+        Random.engine(type: :unknown)
+      end
+    end
+  end
+
+  describe "uniform" do
+    for type <- [{:f, 16}, {:f, 32}, {:f, 64}] do
+      test "generates #{Nx.Type.to_string(type)} numbers in [0, 1)" do
+        t = Random.uniform(Random.engine(), {10, 10}, type: unquote(type))
+
+        assert t.shape == {10, 10}
+        assert t.type == unquote(type)
+        assert Nx.to_number(Nx.reduce_min(t)) >= 0
+        assert Nx.to_number(Nx.reduce_max(t)) < 1
+      end
+    end
+
+    test "generates numbers within the bounds" do
+      t = Random.uniform(Random.engine(), {100}, min: -3.0, max: -2.0)
+
+      assert Nx.to_number(Nx.reduce_min(t)) >= -3
+      assert Nx.to_number(Nx.reduce_max(t)) <= -2
+    end
+
+    test "raises on integer types" do
+      assert_raise RuntimeError, ~r/unsupported dtype: s32/, fn ->
+        Random.uniform(Random.engine(), {2}, type: {:s, 32})
+      end
+    end
+  end
+
+  describe "normal" do
+    for type <- [{:f, 16}, {:f, 32}, {:f, 64}] do
+      test "generates #{Nx.Type.to_string(type)} numbers" do
+        t = Random.normal(Random.engine(), {10, 10}, type: unquote(type))
+
+        assert t.shape == {10, 10}
+        assert t.type == unquote(type)
+      end
+    end
+
+    test "generates numbers with the given mean and standard deviation" do
+      t = Random.normal(Random.engine(seed: 3), {10_000}, mean: 5.0, standard_deviation: 0.5)
+
+      assert_in_delta Nx.to_number(Nx.mean(t)), 5.0, 0.05
+      assert_in_delta Nx.to_number(Nx.standard_deviation(t)), 0.5, 0.05
+    end
+  end
+end